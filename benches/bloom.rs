@@ -0,0 +1,59 @@
+//! Per-frame bloom benchmark.
+//!
+//! Run with `cargo bench --bench bloom`. Compares the allocating `bloom` against `bloom_into`
+//! reusing one output buffer, the way a video pipeline would call it for every frame.
+
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use imgfx::{bloom, bloom_into, BloomOptions};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+const FRAMES: u32 = 30;
+
+fn test_frame() -> RgbaImage {
+    ImageBuffer::from_fn(WIDTH, HEIGHT, |x, y| {
+        let r = (x * 255 / WIDTH) as u8;
+        let g = (y * 255 / HEIGHT) as u8;
+        let b = ((x ^ y) & 0xFF) as u8;
+        Rgba([r, g, b, 255])
+    })
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let per_frame = elapsed / FRAMES;
+    println!(
+        "{:<12} {:>10.2?} / frame  ({:.1} fps)",
+        name,
+        per_frame,
+        1.0 / per_frame.as_secs_f64()
+    );
+}
+
+fn main() {
+    let frame = test_frame();
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        let img = DynamicImage::ImageRgba8(frame.clone());
+        black_box(bloom(img, 1.0, 8.0, 128, None, &BloomOptions::default()));
+    }
+    report("bloom", start.elapsed());
+
+    let mut output: RgbaImage = ImageBuffer::new(WIDTH, HEIGHT);
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        bloom_into(
+            &frame,
+            &mut output,
+            1.0,
+            8.0,
+            128,
+            None,
+            &BloomOptions::default(),
+        );
+        black_box(&output);
+    }
+    report("bloom_into", start.elapsed());
+}
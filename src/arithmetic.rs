@@ -1,20 +1,25 @@
-use crate::utils::{get_channel_by_name_rgb_color, get_channel_by_name_rgba_u8};
+use crate::utils::{
+    get_channel_by_name_rgb_color, get_channel_by_name_rgba_u8, mix_pixels, Mix, Operand, Overflow,
+};
 use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba, RgbaImage};
 use rayon::prelude::*;
 
 /// Add blend mode operation.
-/// Adds the input color RGB to each pixel's RGB. Overflows.
+/// Adds the input color RGB to each pixel's RGB.
 /// RGB channels are remappable before operation.
 /// * `img` - The image::DynamicImage input to perform the operation on.
 /// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
 /// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
 /// * `color` - Temporarily based on the Clap RgbColor struct. The right-hand side of the operation.
-/// * `raw` - bool, if true, allow for u8 overflow, else, clamp at 255.
+/// * `overflow` - How results outside of 0-255 are handled, see Overflow.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
 pub fn add(
     img: DynamicImage,
     lhs: Option<Vec<String>>,
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
+    overflow: Overflow,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -45,53 +50,48 @@ pub fn add(
             None => (in_pixel[0], in_pixel[1], in_pixel[2]),
         };
 
-        let (r, g, b) = {
-            let r = (lhs.0 as i32 + rhs.0 as i32).min(255) as u8;
-            let g = (lhs.1 as i32 + rhs.1 as i32).min(255) as u8;
-            let b = (lhs.2 as i32 + rhs.2 as i32).min(255) as u8;
-
-            (r, g, b)
-        };
+        let (r, g, b) = (
+            overflow.apply(lhs.0 as i64 + rhs.0 as i64),
+            overflow.apply(lhs.1 as i64 + rhs.1 as i64),
+            overflow.apply(lhs.2 as i64 + rhs.2 as i64),
+        );
 
         let a = in_pixel[3];
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
 }
 
 /// Subtraction blend mode operation.
-/// Subtracts the input color RGB from each pixel's RGB.
+/// Subtracts the operand RGB from each pixel's RGB.
 /// RGB channels are remappable before operation.
 /// * `img` - The image::DynamicImage input to perform the operation on.
 /// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
 /// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
-/// * `color` - Temporarily based on the Clap RgbColor struct. The right-hand side of the operation.
-/// * `raw` - bool, if true, allow for u8 underflow, else, get the absolute value of the operation.
+/// * `operand` - The right-hand side of the operation, a color or an image. An empty image only
+///   applies the lhs remapping.
+/// * `overflow` - How results outside of 0-255 are handled, see Overflow.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
 pub fn sub(
     img: DynamicImage,
     lhs: Option<Vec<String>>,
     rhs: Option<Vec<String>>,
-    color: Rgb<u8>,
-    raw: bool,
+    operand: impl Into<Operand>,
+    overflow: Overflow,
+    mix: Option<Mix>,
 ) -> RgbaImage {
-    let r = color.0[0];
-    let g = color.0[1];
-    let b = color.0[2];
+    let operand = operand.into();
+
+    // An empty operand image has nothing to tile
+    if operand.is_empty() {
+        return remap_lhs(&img, &lhs);
+    }
 
     let (width, height) = img.dimensions();
 
     let mut output: RgbaImage = ImageBuffer::new(width, height);
 
-    let rhs = match rhs {
-        Some(rhs) => (
-            get_channel_by_name_rgb_color(&rhs[0], &color),
-            get_channel_by_name_rgb_color(&rhs[1], &color),
-            get_channel_by_name_rgb_color(&rhs[2], &color),
-        ),
-        None => (r, g, b),
-    };
-
     output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
         let in_pixel = img.get_pixel(x, y);
 
@@ -104,32 +104,23 @@ pub fn sub(
             None => (in_pixel[0], in_pixel[1], in_pixel[2]),
         };
 
-        let (r, g, b) = match raw {
-            true => {
-                let r = lhs.0 - rhs.0;
-                let g = lhs.1 - rhs.1;
-                let b = lhs.2 - rhs.2;
-
-                (r, g, b)
-            }
-            false => {
-                let r = (lhs.0 as i32 - rhs.0 as i32).abs() as u8;
-                let g = (lhs.1 as i32 - rhs.1 as i32).abs() as u8;
-                let b = (lhs.2 as i32 - rhs.2 as i32).abs() as u8;
-
-                (r, g, b)
-            }
-        };
+        let rhs = operand.get_rgb(x, y, &rhs);
+
+        let (r, g, b) = (
+            overflow.apply(lhs.0 as i64 - rhs.0 as i64),
+            overflow.apply(lhs.1 as i64 - rhs.1 as i64),
+            overflow.apply(lhs.2 as i64 - rhs.2 as i64),
+        );
 
         let a = in_pixel[3];
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
 }
 
 /// Multiplication blend mode operation.
-/// Multiplies each pixel's RGB by the RGB of the color param. Overflows.
+/// Multiplies each pixel's RGB by the RGB of the color param.
 ///
 /// This operation often results in images with very extreme color noise and as such will
 /// take up significantly more storage than the inputted image. Sometimes up to 36 times larger
@@ -140,11 +131,15 @@ pub fn sub(
 /// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
 /// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
 /// * `color` - Temporarily based on the Clap RgbColor struct. The right-hand side of the operation.
+/// * `overflow` - How results outside of 0-255 are handled, see Overflow.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
 pub fn mult(
     img: DynamicImage,
     lhs: Option<Vec<String>>,
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
+    overflow: Overflow,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -175,12 +170,12 @@ pub fn mult(
             None => (in_pixel[0], in_pixel[1], in_pixel[2]),
         };
 
-        let r = lhs.0.wrapping_mul(rhs.0);
-        let g = lhs.1.wrapping_mul(rhs.1);
-        let b = lhs.2.wrapping_mul(rhs.2);
+        let r = overflow.apply(lhs.0 as i64 * rhs.0 as i64);
+        let g = overflow.apply(lhs.1 as i64 * rhs.1 as i64);
+        let b = overflow.apply(lhs.2 as i64 * rhs.2 as i64);
         let a = in_pixel[3];
 
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
@@ -188,7 +183,7 @@ pub fn mult(
 
 /// Exponential blend mode operation.
 /// Calculates the power of each channel where the image pixel RGB is the base and the color param
-/// RGB is the exponent. Overflows.
+/// RGB is the exponent.
 ///
 /// This operation often results in images with very extreme color noise and as such will
 /// take up significantly more storage than the inputted image. Sometimes up to 36 times larger
@@ -199,11 +194,15 @@ pub fn mult(
 /// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
 /// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
 /// * `color` - Temporarily based on the Clap RgbColor struct. The right-hand side of the operation.
+/// * `overflow` - How results outside of 0-255 are handled, see Overflow.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
 pub fn pow(
     img: DynamicImage,
     lhs: Option<Vec<String>>,
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
+    overflow: Overflow,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -234,20 +233,27 @@ pub fn pow(
             None => (in_pixel[0], in_pixel[1], in_pixel[2]),
         };
 
-        let r = lhs.0.wrapping_pow(rhs.0 as u32) as u8;
-        let g = lhs.1.wrapping_pow(rhs.1 as u32) as u8;
-        let b = lhs.2.wrapping_pow(rhs.2 as u32) as u8;
+        // Wrapping keeps the low bits exact, the other modes only care that the value is large
+        let pow = |base: u8, exp: u8| match overflow {
+            Overflow::Wrap => (base as i64).wrapping_pow(exp as u32),
+            _ => (base as i64).saturating_pow(exp as u32),
+        };
+
+        let r = overflow.apply(pow(lhs.0, rhs.0));
+        let g = overflow.apply(pow(lhs.1, rhs.1));
+        let b = overflow.apply(pow(lhs.2, rhs.2));
 
         let a = in_pixel[3];
 
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
 }
 
 /// Division blend mode operation.
-/// Divides the image pixel RGB by the color param RGB.
+/// Divides the image pixel RGB by the color param RGB. The result never leaves 0-255, so there is
+/// no overflow parameter. Division by zero is treated as division by one.
 ///
 /// This operation is quite useful for isolating or removing particular colors when used with the
 /// lhs remapping.
@@ -257,11 +263,13 @@ pub fn pow(
 /// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
 /// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
 /// * `color` - Temporarily based on the Clap RgbColor struct. The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
 pub fn div(
     img: DynamicImage,
     lhs: Option<Vec<String>>,
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -297,7 +305,265 @@ pub fn div(
         let b = lhs.2 / rhs.2.max(1);
         let a = in_pixel[3];
 
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
+    });
+
+    output
+}
+
+/// Modulo operation.
+/// Remainder of each pixel's RGB divided by the operand RGB. Produces banding as the channel
+/// repeatedly climbs and drops back to zero. A zero operand channel leaves the channel as is.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `operand` - The right-hand side of the operation, a color or an image. An empty image only
+///   applies the lhs remapping.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn modulo(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    operand: impl Into<Operand>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let operand = operand.into();
+
+    // An empty operand image has nothing to tile
+    if operand.is_empty() {
+        return remap_lhs(&img, &lhs);
+    }
+
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let lhs = match lhs {
+            Some(ref lhs) => (
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+            ),
+            None => (in_pixel[0], in_pixel[1], in_pixel[2]),
+        };
+
+        let rhs = operand.get_rgb(x, y, &rhs);
+
+        let r = if rhs.0 == 0 { lhs.0 } else { lhs.0 % rhs.0 };
+        let g = if rhs.1 == 0 { lhs.1 } else { lhs.1 % rhs.1 };
+        let b = if rhs.2 == 0 { lhs.2 } else { lhs.2 % rhs.2 };
+        let a = in_pixel[3];
+
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
+    });
+
+    output
+}
+
+/// Minimum operation.
+/// Keeps the smaller of each pixel's channel and the operand channel.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `operand` - The right-hand side of the operation, a color or an image. An empty image only
+///   applies the lhs remapping.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn min_channels(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    operand: impl Into<Operand>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let operand = operand.into();
+
+    // An empty operand image has nothing to tile
+    if operand.is_empty() {
+        return remap_lhs(&img, &lhs);
+    }
+
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let lhs = match lhs {
+            Some(ref lhs) => (
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+            ),
+            None => (in_pixel[0], in_pixel[1], in_pixel[2]),
+        };
+
+        let rhs = operand.get_rgb(x, y, &rhs);
+
+        let r = lhs.0.min(rhs.0);
+        let g = lhs.1.min(rhs.1);
+        let b = lhs.2.min(rhs.2);
+        let a = in_pixel[3];
+
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
+    });
+
+    output
+}
+
+/// Maximum operation.
+/// Keeps the larger of each pixel's channel and the operand channel.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `operand` - The right-hand side of the operation, a color or an image. An empty image only
+///   applies the lhs remapping.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn max_channels(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    operand: impl Into<Operand>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let operand = operand.into();
+
+    // An empty operand image has nothing to tile
+    if operand.is_empty() {
+        return remap_lhs(&img, &lhs);
+    }
+
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let lhs = match lhs {
+            Some(ref lhs) => (
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+            ),
+            None => (in_pixel[0], in_pixel[1], in_pixel[2]),
+        };
+
+        let rhs = operand.get_rgb(x, y, &rhs);
+
+        let r = lhs.0.max(rhs.0);
+        let g = lhs.1.max(rhs.1);
+        let b = lhs.2.max(rhs.2);
+        let a = in_pixel[3];
+
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
+    });
+
+    output
+}
+
+/// Absolute difference operation.
+/// The absolute difference between each pixel's channel and the operand channel.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `operand` - The right-hand side of the operation, a color or an image. An empty image only
+///   applies the lhs remapping.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn absdiff(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    operand: impl Into<Operand>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    sub(img, lhs, rhs, operand, Overflow::AbsDiff, mix)
+}
+
+/// Weighted average operation.
+/// Linearly interpolates between each pixel's RGB and the operand RGB.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `operand` - The right-hand side of the operation, a color or an image. An empty image only
+///   applies the lhs remapping.
+/// * `weight` - 0.0-1.0, the weight of the operand. 0.5 is a plain average.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn avg_weighted(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    operand: impl Into<Operand>,
+    weight: f64,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let operand = operand.into();
+
+    // An empty operand image has nothing to tile
+    if operand.is_empty() {
+        return remap_lhs(&img, &lhs);
+    }
+
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    let weight = weight.clamp(0.0, 1.0);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let lhs = match lhs {
+            Some(ref lhs) => (
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+            ),
+            None => (in_pixel[0], in_pixel[1], in_pixel[2]),
+        };
+
+        let rhs = operand.get_rgb(x, y, &rhs);
+
+        let weighted = |l: u8, r: u8| (l as f64 * (1.0 - weight) + r as f64 * weight).round() as u8;
+
+        let r = weighted(lhs.0, rhs.0);
+        let g = weighted(lhs.1, rhs.1);
+        let b = weighted(lhs.2, rhs.2);
+        let a = in_pixel[3];
+
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
+    });
+
+    output
+}
+
+/// The input with only the lhs remapping applied, what the operand operations return for an
+/// empty operand image.
+fn remap_lhs(img: &DynamicImage, lhs: &Option<Vec<String>>) -> RgbaImage {
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        *pixel = match lhs {
+            Some(lhs) => Rgba([
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+                in_pixel[3],
+            ]),
+            None => in_pixel,
+        };
     });
 
     output
@@ -334,7 +600,14 @@ mod tests {
         let red = load_image("ff0000.png".to_string());
         let control_color = get_color_from_control(red.clone());
 
-        let out = add(red.clone(), None, None, Rgb([0, 0, 255]));
+        let out = add(
+            red.clone(),
+            None,
+            None,
+            Rgb([0, 0, 255]),
+            Overflow::Clamp,
+            None,
+        );
 
         println!(
             "{:?} == {:?}",
@@ -352,7 +625,14 @@ mod tests {
         let red = load_image("ff0000.png".to_string());
         let control_color = get_color_from_control(red.clone());
 
-        let out = sub(red.clone(), None, None, Rgb([0, 0, 255]), false);
+        let out = sub(
+            red.clone(),
+            None,
+            None,
+            Rgb([0, 0, 255]),
+            Overflow::AbsDiff,
+            None,
+        );
 
         println!(
             "{:?} == {:?}",
@@ -370,7 +650,14 @@ mod tests {
         let red = load_image("ff0000.png".to_string());
         let control_color = get_color_from_control(red.clone());
 
-        let out = mult(red.clone(), None, None, Rgb([0, 0, 255]));
+        let out = mult(
+            red.clone(),
+            None,
+            None,
+            Rgb([0, 0, 255]),
+            Overflow::Wrap,
+            None,
+        );
 
         println!(
             "{:?} == {:?}",
@@ -388,7 +675,7 @@ mod tests {
         let red = load_image("ff0000.png".to_string());
         let control_color = get_color_from_control(red.clone());
 
-        let out = div(red.clone(), None, None, Rgb([0, 0, 255]));
+        let out = div(red.clone(), None, None, Rgb([0, 0, 255]), None);
 
         println!(
             "{:?} == {:?}",
@@ -400,4 +687,104 @@ mod tests {
 
         assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
     }
+
+    #[test]
+    fn test_overflow() {
+        let red = load_image("ff0000.png".to_string());
+
+        let cases = [
+            (Overflow::Clamp, Rgb([255, 0, 0])),
+            (Overflow::Wrap, Rgb([1, 0, 0])),
+            (Overflow::Mirror, Rgb([253, 0, 0])),
+            (Overflow::AbsDiff, Rgb([255, 0, 0])),
+        ];
+
+        for (overflow, expected) in cases {
+            let out = add(red.clone(), None, None, Rgb([2, 0, 0]), overflow, None);
+
+            assert_eq!(out.get_pixel(0, 0).to_rgb(), expected, "{:?}", overflow);
+        }
+
+        let cases = [
+            (Overflow::Clamp, Rgb([255, 0, 0])),
+            (Overflow::Wrap, Rgb([255, 246, 0])),
+            (Overflow::Mirror, Rgb([255, 10, 0])),
+            (Overflow::AbsDiff, Rgb([255, 10, 0])),
+        ];
+
+        for (overflow, expected) in cases {
+            let out = sub(red.clone(), None, None, Rgb([0, 10, 0]), overflow, None);
+
+            assert_eq!(out.get_pixel(0, 0).to_rgb(), expected, "{:?}", overflow);
+        }
+    }
+
+    #[test]
+    fn test_modulo() {
+        let red = load_image("ff0000.png".to_string());
+
+        let out = modulo(red.clone(), None, None, Rgb([100, 0, 7]), None);
+
+        const EXPECTED: Rgb<u8> = Rgb([55, 0, 0]);
+
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
+    }
+
+    #[test]
+    fn test_min_max() {
+        let red = load_image("ff0000.png".to_string());
+
+        let out = min_channels(red.clone(), None, None, Rgb([100, 50, 7]), None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([100, 0, 0]));
+
+        let out = max_channels(red.clone(), None, None, Rgb([100, 50, 7]), None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([255, 50, 7]));
+    }
+
+    #[test]
+    fn test_absdiff_image_operand() {
+        let red = load_image("ff0000.png".to_string());
+        let operand =
+            DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba([55, 20, 255, 255])));
+
+        let out = absdiff(
+            red.clone(),
+            None,
+            Some(vec!["b".to_string(), "g".to_string(), "r".to_string()]),
+            operand,
+            None,
+        );
+
+        const EXPECTED: Rgb<u8> = Rgb([0, 20, 55]);
+
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
+    }
+
+    #[test]
+    fn test_avg_weighted() {
+        let red = load_image("ff0000.png".to_string());
+
+        let out = avg_weighted(red.clone(), None, None, Rgb([0, 0, 200]), 0.25, None);
+
+        const EXPECTED: Rgb<u8> = Rgb([191, 0, 50]);
+
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
+    }
+
+    #[test]
+    fn test_empty_operand() {
+        let red = load_image("ff0000.png".to_string());
+        let empty = || Operand::Image(DynamicImage::new_rgba8(0, 0));
+
+        let out = modulo(red.clone(), None, None, empty(), None);
+        assert_eq!(out, red.to_rgba8());
+
+        let out = absdiff(red.clone(), None, None, empty(), None);
+        assert_eq!(out, red.to_rgba8());
+
+        // The lhs remapping still applies
+        let lhs = Some(vec!["b".to_string(), "g".to_string(), "r".to_string()]);
+        let out = min_channels(red.clone(), lhs, None, empty(), None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([0, 0, 255]));
+    }
 }
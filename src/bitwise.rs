@@ -1,6 +1,9 @@
-use crate::utils::{get_channel_by_name_rgb_color, get_channel_by_name_rgba_u8};
+use crate::utils::{
+    get_channel_by_name_rgb_color, get_channel_by_name_rgba_u8, mix_pixels, Channel, Mix, Overflow,
+};
 use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba, RgbaImage};
 use rayon::prelude::*;
+use std::str::FromStr;
 
 pub fn or(
     img: DynamicImage,
@@ -8,6 +11,7 @@ pub fn or(
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
     negate: bool,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -45,7 +49,7 @@ pub fn or(
 
         let a = in_pixel[3];
 
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
@@ -57,6 +61,7 @@ pub fn and(
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
     negate: bool,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -94,7 +99,7 @@ pub fn and(
 
         let a = in_pixel[3];
 
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
@@ -106,6 +111,7 @@ pub fn xor(
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
     negate: bool,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -143,23 +149,84 @@ pub fn xor(
 
         let a = in_pixel[3];
 
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
 }
 
+#[derive(Copy, Clone)]
 pub enum BitshiftDirection {
     LEFT,
     RIGHT,
 }
 
+/// Shift the bits of each channel by its own amount. Positive amounts shift left, negative
+/// amounts shift right.
+///
+/// Amounts are capped at 8 in either direction, anything larger shifts by 8. A shift of 8 moves
+/// every original bit out of the channel, so right shifts give 0 (or 255 when filling with ones).
+/// Left shifts give `v * 256` plus the fill, which `overflow` brings back into range: Wrap keeps
+/// only the fill, Clamp gives 255 for any non-zero channel and Mirror folds it back down.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `shifts` - Signed shift amount for the R, G and B channels, negative meaning right. Capped
+///   at -8 and 8.
+/// * `fill_ones` - If true, the bits shifted in are ones instead of zeros.
+/// * `overflow` - How left shifts past 255 are handled, see Overflow.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
 pub fn bitshift(
     img: DynamicImage,
-    direction: BitshiftDirection,
     lhs: Option<Vec<String>>,
-    bits: u8,
-    raw: bool,
+    shifts: [i8; 3],
+    fill_ones: bool,
+    overflow: Overflow,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let shift = |v: u8, amount: i8| {
+        let bits = amount.unsigned_abs().min(8) as u32;
+
+        if amount >= 0 {
+            overflow.apply(shift_left(v, bits, fill_ones))
+        } else {
+            shift_right(v, bits, fill_ones)
+        }
+    };
+
+    map_channels(img, lhs, mix, |(r, g, b)| {
+        (
+            shift(r, shifts[0]),
+            shift(g, shifts[1]),
+            shift(b, shifts[2]),
+        )
+    })
+}
+
+/// Shift a channel left at full precision, ready for an Overflow to bring back into range.
+fn shift_left(v: u8, bits: u32, fill_ones: bool) -> i64 {
+    let fill = if fill_ones { (1i64 << bits) - 1 } else { 0 };
+
+    (v as i64) << bits | fill
+}
+
+/// Shift a channel right. Right shifts can't overflow, shifting by 8 clears the channel.
+fn shift_right(v: u8, bits: u32, fill_ones: bool) -> u8 {
+    let shifted = v.checked_shr(bits).unwrap_or(0);
+
+    if fill_ones {
+        shifted | !0xFFu8.checked_shr(bits).unwrap_or(0)
+    } else {
+        shifted
+    }
+}
+
+/// Apply a function to the (remapped) RGB of every pixel, passing alpha through.
+fn map_channels(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    mix: Option<Mix>,
+    f: impl Fn((u8, u8, u8)) -> (u8, u8, u8) + Sync,
 ) -> RgbaImage {
     let (width, height) = img.dimensions();
 
@@ -176,40 +243,258 @@ pub fn bitshift(
             ),
             None => (in_pixel[0], in_pixel[1], in_pixel[2]),
         };
-        let (r, g, b, a) = match direction {
-            BitshiftDirection::LEFT => {
-                if raw {
-                    (
-                        ((lhs.0 as u16) << bits) as u8,
-                        ((lhs.1 as u16) << bits) as u8,
-                        ((lhs.2 as u16) << bits) as u8,
-                        in_pixel[3],
-                    )
-                } else {
-                    (
-                        ((lhs.0 as u16) << bits).min(255) as u8,
-                        ((lhs.1 as u16) << bits).min(255) as u8,
-                        ((lhs.2 as u16) << bits).min(255) as u8,
-                        in_pixel[3],
-                    )
-                }
-            }
-            BitshiftDirection::RIGHT => (
-                (lhs.0.wrapping_shr(bits.into())),
-                (lhs.1.wrapping_shr(bits.into())),
-                (lhs.2.wrapping_shr(bits.into())),
-                in_pixel[3],
-            ),
-        };
-        *pixel = Rgba([r, g, b, a]);
+
+        let (r, g, b) = f(lhs);
+        let a = in_pixel[3];
+
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
 }
 
+/// Rotate the bits of each channel. Bits shifted out of one end come back in at the other, so
+/// unlike bitshift no information is lost.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `direction` - Rotate towards the most (LEFT) or least (RIGHT) significant bit.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `bits` - How many bits to rotate by, taken modulo 8.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn rotate(
+    img: DynamicImage,
+    direction: BitshiftDirection,
+    lhs: Option<Vec<String>>,
+    bits: u8,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let bits = bits as u32;
+
+    map_channels(img, lhs, mix, |(r, g, b)| match direction {
+        BitshiftDirection::LEFT => (
+            r.rotate_left(bits),
+            g.rotate_left(bits),
+            b.rotate_left(bits),
+        ),
+        BitshiftDirection::RIGHT => (
+            r.rotate_right(bits),
+            g.rotate_right(bits),
+            b.rotate_right(bits),
+        ),
+    })
+}
+
+/// Reverse the order of the bits of each channel, so the most significant bit becomes the least
+/// significant.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn reverse_bits(img: DynamicImage, lhs: Option<Vec<String>>, mix: Option<Mix>) -> RgbaImage {
+    map_channels(img, lhs, mix, |(r, g, b)| {
+        (r.reverse_bits(), g.reverse_bits(), b.reverse_bits())
+    })
+}
+
+/// Encode each channel as its Gray code, where consecutive values differ by a single bit.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn gray_encode(img: DynamicImage, lhs: Option<Vec<String>>, mix: Option<Mix>) -> RgbaImage {
+    let encode = |v: u8| v ^ (v >> 1);
+
+    map_channels(img, lhs, mix, |(r, g, b)| (encode(r), encode(g), encode(b)))
+}
+
+/// Decode each channel from Gray code, the inverse of gray_encode.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn gray_decode(img: DynamicImage, lhs: Option<Vec<String>>, mix: Option<Mix>) -> RgbaImage {
+    let decode = |v: u8| {
+        let mut v = v;
+        v ^= v >> 4;
+        v ^= v >> 2;
+        v ^= v >> 1;
+        v
+    };
+
+    map_channels(img, lhs, mix, |(r, g, b)| (decode(r), decode(g), decode(b)))
+}
+
+/// Shift the bits of each pixel as a single 24-bit word, R being the most significant byte and B
+/// the least. Bits cross the channel boundaries, so shifting left by 8 moves G into R and B into
+/// G.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `direction` - Shift towards R (LEFT) or towards B (RIGHT).
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `bits` - How many bits to shift by. Shifting by 24 or more clears the word unless rotating.
+/// * `rotate` - If true, bits shifted out of one end come back in at the other.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn bitshift_word(
+    img: DynamicImage,
+    direction: BitshiftDirection,
+    lhs: Option<Vec<String>>,
+    bits: u8,
+    rotate: bool,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    const MASK: u32 = 0x00FF_FFFF;
+
+    let bits = bits as u32;
+
+    map_channels(img, lhs, mix, |(r, g, b)| {
+        let word = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+
+        let shifted = if rotate {
+            let bits = bits % 24;
+            match direction {
+                BitshiftDirection::LEFT => (word << bits | word >> ((24 - bits) % 24)) & MASK,
+                BitshiftDirection::RIGHT => (word >> bits | word << ((24 - bits) % 24)) & MASK,
+            }
+        } else if bits >= 24 {
+            0
+        } else {
+            match direction {
+                BitshiftDirection::LEFT => (word << bits) & MASK,
+                BitshiftDirection::RIGHT => word >> bits,
+            }
+        };
+
+        ((shifted >> 16) as u8, (shifted >> 8) as u8, shifted as u8)
+    })
+}
+
+/// A bit plane of a channel, 0 being the least significant bit and 7 the most significant.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BitPlane(u8);
+
+impl BitPlane {
+    /// Errors if `plane` is not 0-7.
+    pub fn new(plane: u8) -> Result<Self, String> {
+        if plane < 8 {
+            Ok(BitPlane(plane))
+        } else {
+            Err(format!("Invalid bit plane: {}", plane))
+        }
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+/// Clap FromStr
+impl FromStr for BitPlane {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let plane = s
+            .trim()
+            .parse::<u8>()
+            .map_err(|_| format!("Invalid bit plane: {}", s))?;
+
+        BitPlane::new(plane)
+    }
+}
+
+/// Extract a single bit plane of a channel as a binary image. Pixels whose bit is set become
+/// white, the rest black. Alpha is passed through.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `channel` - The (remapped) channel to read.
+/// * `plane` - The bit to extract.
+pub fn extract_bit_plane(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    channel: Channel,
+    plane: BitPlane,
+) -> RgbaImage {
+    let (index, plane) = (channel.index(), plane.get());
+
+    map_channels(img, lhs, None, |(r, g, b)| {
+        let v = if [r, g, b][index] >> plane & 1 == 1 {
+            255
+        } else {
+            0
+        };
+
+        (v, v, v)
+    })
+}
+
+/// Swap a bit plane of one channel with a bit plane of another (or the same) channel.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `a` - The first (remapped) channel and plane, e.g. (Channel::R, 7).
+/// * `b` - The second (remapped) channel and plane, e.g. (Channel::B, 0).
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn swap_bit_planes(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    a: (Channel, BitPlane),
+    b: (Channel, BitPlane),
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let (a_index, a_plane) = (a.0.index(), a.1.get());
+    let (b_index, b_plane) = (b.0.index(), b.1.get());
+
+    map_channels(img, lhs, mix, |(r, g, b)| {
+        let mut channels = [r, g, b];
+
+        let a_bit = channels[a_index] >> a_plane & 1;
+        let b_bit = channels[b_index] >> b_plane & 1;
+
+        channels[a_index] = channels[a_index] & !(1 << a_plane) | b_bit << a_plane;
+        channels[b_index] = channels[b_index] & !(1 << b_plane) | a_bit << b_plane;
+
+        (channels[0], channels[1], channels[2])
+    })
+}
+
+/// Reassemble each channel from its bit planes in a chosen order. Bit `i` of the output is bit
+/// `order[i]` of the input, so [0, 1, 2, 3, 4, 5, 6, 7] is the identity and
+/// [7, 6, 5, 4, 3, 2, 1, 0] reverses the bits. Planes may be repeated or left out.
+///
+/// Panics if any plane in `order` is not 0-7.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `order` - The source plane of each output bit, least significant first.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn reorder_bit_planes(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    order: [u8; 8],
+    mix: Option<Mix>,
+) -> RgbaImage {
+    assert!(
+        order.iter().all(|plane| *plane < 8),
+        "Invalid bit plane order: {:?}",
+        order
+    );
+
+    let reorder = |v: u8| {
+        order
+            .iter()
+            .enumerate()
+            .fold(0u8, |acc, (bit, plane)| acc | (v >> plane & 1) << bit)
+    };
+
+    map_channels(img, lhs, mix, |(r, g, b)| {
+        (reorder(r), reorder(g), reorder(b))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::test_images::solid;
     use image::{Pixel, Rgb};
     use std::env;
     use std::path::PathBuf;
@@ -240,10 +525,11 @@ mod tests {
 
         let out = bitshift(
             red.clone(),
-            BitshiftDirection::LEFT,
             Some(vec!["r".to_string(), "g".to_string(), "b".to_string()]),
-            1,
+            [1, 1, 1],
             false,
+            Overflow::Clamp,
+            None,
         );
 
         println!(
@@ -264,10 +550,11 @@ mod tests {
 
         let out = bitshift(
             red.clone(),
-            BitshiftDirection::RIGHT,
             Some(vec!["r".to_string(), "g".to_string(), "b".to_string()]),
-            1,
+            [-1, -1, -1],
             false,
+            Overflow::Clamp,
+            None,
         );
 
         println!(
@@ -292,6 +579,7 @@ mod tests {
             None,
             Rgb([0, 0, 255]),
             false,
+            None,
         );
 
         println!(
@@ -316,6 +604,7 @@ mod tests {
             None,
             Rgb([0, 0, 255]),
             false,
+            None,
         );
 
         println!(
@@ -340,6 +629,7 @@ mod tests {
             None,
             Rgb([0, 0, 255]),
             false,
+            None,
         );
 
         println!(
@@ -352,4 +642,196 @@ mod tests {
 
         assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
     }
+
+    #[test]
+    fn test_xor_mix() {
+        let red = load_image("ff0000.png".to_string());
+        let control_color = get_color_from_control(red.clone());
+
+        let out = xor(
+            red.clone(),
+            None,
+            None,
+            Rgb([0, 255, 255]),
+            false,
+            Some(Mix::per_channel(0.3, 0.3, 1.0)),
+        );
+
+        println!(
+            "{:?} == {:?}",
+            control_color,
+            out.get_pixel(0, 0).to_rgb().0
+        );
+
+        const EXPECTED: Rgb<u8> = Rgb([255, 77, 255]);
+
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
+    }
+
+    #[test]
+    fn test_rotate() {
+        let img = solid(1, 1, Rgba([0b1000_0001, 0b0000_0011, 0b1100_0000, 255]));
+
+        let out = rotate(img.clone(), BitshiftDirection::LEFT, None, 1, None);
+        assert_eq!(
+            out.get_pixel(0, 0).to_rgb(),
+            Rgb([0b0000_0011, 0b0000_0110, 0b1000_0001])
+        );
+
+        let out = rotate(img, BitshiftDirection::RIGHT, None, 9, None);
+        assert_eq!(
+            out.get_pixel(0, 0).to_rgb(),
+            Rgb([0b1100_0000, 0b1000_0001, 0b0110_0000])
+        );
+    }
+
+    #[test]
+    fn test_reverse_bits() {
+        let img = solid(1, 1, Rgba([0b1000_0000, 0b0000_0011, 0b1010_0000, 7]));
+
+        let out = reverse_bits(img, None, None);
+
+        assert_eq!(
+            *out.get_pixel(0, 0),
+            Rgba([0b0000_0001, 0b1100_0000, 0b0000_0101, 7])
+        );
+    }
+
+    #[test]
+    fn test_gray_code_round_trip() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(256, 1, |x, _| {
+            Rgba([x as u8, 255 - x as u8, (x * 7) as u8, 255])
+        }));
+
+        let encoded = gray_encode(img.clone(), None, None);
+
+        // Consecutive values differ by exactly one bit
+        for x in 1..256 {
+            let diff = encoded.get_pixel(x, 0)[0] ^ encoded.get_pixel(x - 1, 0)[0];
+            assert_eq!(diff.count_ones(), 1);
+        }
+
+        let decoded = gray_decode(DynamicImage::ImageRgba8(encoded), None, None);
+
+        assert_eq!(decoded, img.to_rgba8());
+    }
+
+    #[test]
+    fn test_bitshift_word() {
+        let img = solid(1, 1, Rgba([0x12, 0x34, 0x56, 255]));
+
+        let out = bitshift_word(img.clone(), BitshiftDirection::LEFT, None, 8, false, None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([0x34, 0x56, 0x00]));
+
+        let out = bitshift_word(img.clone(), BitshiftDirection::RIGHT, None, 4, false, None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([0x01, 0x23, 0x45]));
+
+        let out = bitshift_word(img, BitshiftDirection::RIGHT, None, 4, true, None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([0x61, 0x23, 0x45]));
+    }
+
+    #[test]
+    fn test_extract_bit_plane() {
+        let red = load_image("ff0000.png".to_string());
+        let plane = BitPlane::new(3).unwrap();
+
+        let out = extract_bit_plane(red.clone(), None, Channel::R, plane);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([255, 255, 255]));
+
+        let out = extract_bit_plane(red.clone(), None, Channel::G, plane);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([0, 0, 0]));
+
+        // The channel is read after the lhs remapping
+        let lhs = Some(vec!["b".to_string(), "r".to_string(), "b".to_string()]);
+        let out = extract_bit_plane(red.clone(), lhs, Channel::G, plane);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn test_bit_plane_from_str() {
+        assert_eq!("7".parse::<BitPlane>(), BitPlane::new(7));
+        assert!("8".parse::<BitPlane>().is_err());
+        assert!("-1".parse::<BitPlane>().is_err());
+        assert!(BitPlane::new(8).is_err());
+    }
+
+    #[test]
+    fn test_swap_bit_planes() {
+        let img = solid(1, 1, Rgba([0b1000_0000, 0b0000_0000, 0b0000_0001, 255]));
+        let plane = |plane: u8| BitPlane::new(plane).unwrap();
+
+        let out = swap_bit_planes(
+            img,
+            None,
+            (Channel::R, plane(7)),
+            (Channel::G, plane(0)),
+            None,
+        );
+
+        assert_eq!(
+            out.get_pixel(0, 0).to_rgb(),
+            Rgb([0b0000_0000, 0b0000_0001, 0b0000_0001])
+        );
+    }
+
+    #[test]
+    fn test_reorder_bit_planes() {
+        let img = solid(1, 1, Rgba([0b1000_0001, 0b0101_0000, 0b0000_1111, 255]));
+
+        let out = reorder_bit_planes(img.clone(), None, [7, 6, 5, 4, 3, 2, 1, 0], None);
+        assert_eq!(
+            out,
+            reverse_bits(img.clone(), None, None),
+            "reversed plane order is bit reversal"
+        );
+
+        let out = reorder_bit_planes(img, None, [0, 0, 0, 0, 0, 0, 0, 0], None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([255, 0, 255]));
+    }
+
+    #[test]
+    fn test_bitshift_per_channel() {
+        let img = solid(1, 1, Rgba([0b0000_0011, 0b1100_0000, 0b0001_1000, 255]));
+
+        let out = bitshift(img.clone(), None, [2, -3, 0], false, Overflow::Wrap, None);
+        assert_eq!(
+            out.get_pixel(0, 0).to_rgb(),
+            Rgb([0b0000_1100, 0b0001_1000, 0b0001_1000])
+        );
+
+        let out = bitshift(img, None, [2, -3, 1], true, Overflow::Wrap, None);
+        assert_eq!(
+            out.get_pixel(0, 0).to_rgb(),
+            Rgb([0b0000_1111, 0b1111_1000, 0b0011_0001])
+        );
+    }
+
+    #[test]
+    fn test_bitshift_large_amounts() {
+        let img = solid(1, 1, Rgba([1, 0, 200, 255]));
+
+        // Amounts past 8 are capped, so they match a shift of 8
+        for shift in [8, 9, 16, 63, 127] {
+            let out = bitshift(img.clone(), None, [shift; 3], false, Overflow::Wrap, None);
+            assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([0, 0, 0]));
+
+            let out = bitshift(img.clone(), None, [shift; 3], true, Overflow::Wrap, None);
+            assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([255, 255, 255]));
+
+            let out = bitshift(img.clone(), None, [shift; 3], false, Overflow::Clamp, None);
+            assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([255, 0, 255]));
+
+            // 256 folds to 254, 200 * 256 = 51200 folds to 200
+            let out = bitshift(img.clone(), None, [shift; 3], false, Overflow::Mirror, None);
+            assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([254, 0, 200]));
+        }
+
+        for shift in [-8, -9, -128] {
+            let out = bitshift(img.clone(), None, [shift; 3], false, Overflow::Clamp, None);
+            assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([0, 0, 0]));
+
+            let out = bitshift(img.clone(), None, [shift; 3], true, Overflow::Clamp, None);
+            assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([255, 255, 255]));
+        }
+    }
 }
@@ -1,12 +1,14 @@
-use crate::utils::{get_channel_by_name_rgb_color, get_channel_by_name_rgba_u8};
+use crate::utils::{get_channel_by_name_rgb_color, get_channel_by_name_rgba_u8, mix_pixels, Mix};
 use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba, RgbaImage};
 use rayon::prelude::*;
+use std::str::FromStr;
 
 pub fn overlay(
     img: DynamicImage,
     lhs: Option<Vec<String>>,
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -57,7 +59,7 @@ pub fn overlay(
 
         let a = in_pixel[3];
 
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
@@ -68,6 +70,7 @@ pub fn screen(
     lhs: Option<Vec<String>>,
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -106,12 +109,676 @@ pub fn screen(
 
         let a = in_pixel[3];
 
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
 }
 
+/// Blend modes following the W3C Compositing and Blending spec, plus the common Photoshop
+/// extensions. The backdrop is the image pixel (lhs) and the source is the color (rhs). All maths
+/// is done on channels normalised to 0.0-1.0.
+///
+/// Hue, Saturation, Color and Luminosity are non-separable, they mix the channels together
+/// instead of blending each one on its own.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    LinearDodge,
+    LinearBurn,
+    HardLight,
+    SoftLight,
+    VividLight,
+    LinearLight,
+    PinLight,
+    HardMix,
+    Difference,
+    Exclusion,
+    Subtract,
+    Divide,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+/// Clap FromStr
+impl FromStr for BlendMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['-', '_'], "").as_str() {
+            "normal" => Ok(BlendMode::Normal),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            "overlay" => Ok(BlendMode::Overlay),
+            "darken" => Ok(BlendMode::Darken),
+            "lighten" => Ok(BlendMode::Lighten),
+            "colordodge" => Ok(BlendMode::ColorDodge),
+            "colorburn" => Ok(BlendMode::ColorBurn),
+            "lineardodge" => Ok(BlendMode::LinearDodge),
+            "linearburn" => Ok(BlendMode::LinearBurn),
+            "hardlight" => Ok(BlendMode::HardLight),
+            "softlight" => Ok(BlendMode::SoftLight),
+            "vividlight" => Ok(BlendMode::VividLight),
+            "linearlight" => Ok(BlendMode::LinearLight),
+            "pinlight" => Ok(BlendMode::PinLight),
+            "hardmix" => Ok(BlendMode::HardMix),
+            "difference" => Ok(BlendMode::Difference),
+            "exclusion" => Ok(BlendMode::Exclusion),
+            "subtract" => Ok(BlendMode::Subtract),
+            "divide" => Ok(BlendMode::Divide),
+            "hue" => Ok(BlendMode::Hue),
+            "saturation" => Ok(BlendMode::Saturation),
+            "color" => Ok(BlendMode::Color),
+            "luminosity" => Ok(BlendMode::Luminosity),
+
+            _ => Err(format!("Invalid BlendMode name: {}", s)),
+        }
+    }
+}
+
+impl BlendMode {
+    /// Blend a backdrop color with a source color. Channels are normalised to 0.0-1.0.
+    pub fn blend(self, backdrop: [f64; 3], source: [f64; 3]) -> [f64; 3] {
+        match self {
+            BlendMode::Hue => set_lum(set_sat(source, sat(backdrop)), lum(backdrop)),
+            BlendMode::Saturation => set_lum(set_sat(backdrop, sat(source)), lum(backdrop)),
+            BlendMode::Color => set_lum(source, lum(backdrop)),
+            BlendMode::Luminosity => set_lum(backdrop, lum(source)),
+            _ => [
+                self.blend_channel(backdrop[0], source[0]),
+                self.blend_channel(backdrop[1], source[1]),
+                self.blend_channel(backdrop[2], source[2]),
+            ],
+        }
+    }
+
+    /// Blend a single normalised backdrop channel with a source channel.
+    ///
+    /// The non-separable modes can't be computed per channel and return the source channel,
+    /// use `blend` for those.
+    pub fn blend_channel(self, cb: f64, cs: f64) -> f64 {
+        match self {
+            BlendMode::Normal
+            | BlendMode::Hue
+            | BlendMode::Saturation
+            | BlendMode::Color
+            | BlendMode::Luminosity => cs,
+            BlendMode::Multiply => cb * cs,
+            BlendMode::Screen => cb + cs - cb * cs,
+            BlendMode::Overlay => BlendMode::HardLight.blend_channel(cs, cb),
+            BlendMode::Darken => cb.min(cs),
+            BlendMode::Lighten => cb.max(cs),
+            BlendMode::ColorDodge => {
+                if cb == 0.0 {
+                    0.0
+                } else if cs >= 1.0 {
+                    1.0
+                } else {
+                    (cb / (1.0 - cs)).min(1.0)
+                }
+            }
+            BlendMode::ColorBurn => {
+                if cb >= 1.0 {
+                    1.0
+                } else if cs == 0.0 {
+                    0.0
+                } else {
+                    1.0 - ((1.0 - cb) / cs).min(1.0)
+                }
+            }
+            BlendMode::LinearDodge => (cb + cs).min(1.0),
+            BlendMode::LinearBurn => (cb + cs - 1.0).max(0.0),
+            BlendMode::HardLight => {
+                if cs <= 0.5 {
+                    BlendMode::Multiply.blend_channel(cb, 2.0 * cs)
+                } else {
+                    BlendMode::Screen.blend_channel(cb, 2.0 * cs - 1.0)
+                }
+            }
+            BlendMode::SoftLight => {
+                if cs <= 0.5 {
+                    cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+                } else {
+                    let d = if cb <= 0.25 {
+                        ((16.0 * cb - 12.0) * cb + 4.0) * cb
+                    } else {
+                        cb.sqrt()
+                    };
+                    cb + (2.0 * cs - 1.0) * (d - cb)
+                }
+            }
+            BlendMode::VividLight => {
+                if cs <= 0.5 {
+                    BlendMode::ColorBurn.blend_channel(cb, 2.0 * cs)
+                } else {
+                    BlendMode::ColorDodge.blend_channel(cb, 2.0 * (cs - 0.5))
+                }
+            }
+            BlendMode::LinearLight => (cb + 2.0 * cs - 1.0).clamp(0.0, 1.0),
+            BlendMode::PinLight => {
+                if cs <= 0.5 {
+                    cb.min(2.0 * cs)
+                } else {
+                    cb.max(2.0 * cs - 1.0)
+                }
+            }
+            BlendMode::HardMix => {
+                if cb + cs >= 1.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            BlendMode::Difference => (cb - cs).abs(),
+            BlendMode::Exclusion => cb + cs - 2.0 * cb * cs,
+            BlendMode::Subtract => (cb - cs).max(0.0),
+            BlendMode::Divide => {
+                if cs == 0.0 {
+                    if cb == 0.0 {
+                        0.0
+                    } else {
+                        1.0
+                    }
+                } else {
+                    (cb / cs).min(1.0)
+                }
+            }
+        }
+    }
+}
+
+/// Lum from the W3C spec, the perceived luminance of a normalised color.
+fn lum(c: [f64; 3]) -> f64 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+/// ClipColor from the W3C spec, brings a color back into gamut while keeping its luminance.
+fn clip_color(c: [f64; 3]) -> [f64; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+
+    let mut c = c;
+
+    if n < 0.0 {
+        c = c.map(|channel| l + (channel - l) * l / (l - n));
+    }
+
+    if x > 1.0 {
+        c = c.map(|channel| l + (channel - l) * (1.0 - l) / (x - l));
+    }
+
+    c
+}
+
+/// SetLum from the W3C spec, shifts a color to the given luminance.
+fn set_lum(c: [f64; 3], l: f64) -> [f64; 3] {
+    let d = l - lum(c);
+    clip_color(c.map(|channel| channel + d))
+}
+
+/// Sat from the W3C spec, the difference between the largest and smallest channel.
+fn sat(c: [f64; 3]) -> f64 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+/// SetSat from the W3C spec, rescales a color to the given saturation keeping its hue.
+fn set_sat(c: [f64; 3], s: f64) -> [f64; 3] {
+    let max = c[0].max(c[1]).max(c[2]);
+    let min = c[0].min(c[1]).min(c[2]);
+
+    if max > min {
+        c.map(|channel| (channel - min) * s / (max - min))
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+/// Blend the image with a color using any BlendMode.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation, the blend source.
+/// * `mode` - The blend mode to apply.
+/// * `mix` - Optional opacity of the blend per channel, mixed over the (remapped) image. Use
+///   Mix::uniform(opacity) for a single opacity. None is fully opaque.
+pub fn blend(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mode: BlendMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let r = color.0[0];
+    let g = color.0[1];
+    let b = color.0[2];
+
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    let rhs = match rhs {
+        Some(rhs) => (
+            get_channel_by_name_rgb_color(&rhs[0], &color),
+            get_channel_by_name_rgb_color(&rhs[1], &color),
+            get_channel_by_name_rgb_color(&rhs[2], &color),
+        ),
+        None => (r, g, b),
+    };
+
+    let source = [
+        rhs.0 as f64 / 255.0,
+        rhs.1 as f64 / 255.0,
+        rhs.2 as f64 / 255.0,
+    ];
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let lhs = match lhs {
+            Some(ref lhs) => (
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+            ),
+            None => (in_pixel[0], in_pixel[1], in_pixel[2]),
+        };
+
+        let backdrop = [
+            lhs.0 as f64 / 255.0,
+            lhs.1 as f64 / 255.0,
+            lhs.2 as f64 / 255.0,
+        ];
+
+        let [r, g, b] = mode
+            .blend(backdrop, source)
+            .map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8);
+
+        let a = in_pixel[3];
+
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
+    });
+
+    output
+}
+
+/// Multiply blend mode. Multiplies the normalised channels, always darkening.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn multiply(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Multiply, mix)
+}
+
+/// Darken blend mode. Keeps the darker of each channel.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn darken(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Darken, mix)
+}
+
+/// Lighten blend mode. Keeps the lighter of each channel.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn lighten(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Lighten, mix)
+}
+
+/// Color dodge blend mode. Brightens the image by dividing it by the inverted color.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn color_dodge(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::ColorDodge, mix)
+}
+
+/// Color burn blend mode. Darkens the image by dividing its inverse by the color.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn color_burn(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::ColorBurn, mix)
+}
+
+/// Linear dodge blend mode. Adds the normalised channels, clamping at white.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn linear_dodge(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::LinearDodge, mix)
+}
+
+/// Linear burn blend mode. Adds the normalised channels and subtracts white, clamping at black.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn linear_burn(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::LinearBurn, mix)
+}
+
+/// Hard light blend mode. Multiplies or screens depending on the color.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn hard_light(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::HardLight, mix)
+}
+
+/// Soft light blend mode, using the W3C formula. A gentler hard light.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn soft_light(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::SoftLight, mix)
+}
+
+/// Vivid light blend mode. Color burns or color dodges depending on the color.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn vivid_light(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::VividLight, mix)
+}
+
+/// Linear light blend mode. Linear burns or linear dodges depending on the color.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn linear_light(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::LinearLight, mix)
+}
+
+/// Pin light blend mode. Darkens or lightens depending on the color.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn pin_light(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::PinLight, mix)
+}
+
+/// Hard mix blend mode. Each channel becomes 0 or 255 depending on whether the sum of the
+/// normalised channels reaches 1.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn hard_mix(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::HardMix, mix)
+}
+
+/// Difference blend mode. The absolute difference of each channel.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn difference(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Difference, mix)
+}
+
+/// Exclusion blend mode. Like difference, with lower contrast.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn exclusion(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Exclusion, mix)
+}
+
+/// Subtract blend mode. Subtracts the color from the image, clamping at black.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn subtract(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Subtract, mix)
+}
+
+/// Divide blend mode. Divides the image by the color, clamping at white.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn divide(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Divide, mix)
+}
+
+/// Hue blend mode. Takes the hue of the color with the saturation and luminosity of the
+/// image.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional opacity of the blend per channel, mixed over the (remapped) image. Use
+///   Mix::uniform(opacity) for a single opacity. None is fully opaque.
+pub fn blend_hue(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Hue, mix)
+}
+
+/// Saturation blend mode. Takes the saturation of the color with the hue and luminosity of
+/// the image.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional opacity of the blend per channel, mixed over the (remapped) image. Use
+///   Mix::uniform(opacity) for a single opacity. None is fully opaque.
+pub fn blend_saturation(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Saturation, mix)
+}
+
+/// Color blend mode. Takes the hue and saturation of the color with the luminosity of the
+/// image. Useful for colourising greyscale images.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional opacity of the blend per channel, mixed over the (remapped) image. Use
+///   Mix::uniform(opacity) for a single opacity. None is fully opaque.
+pub fn blend_color(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Color, mix)
+}
+
+/// Luminosity blend mode. Takes the luminosity of the color with the hue and saturation of
+/// the image.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional opacity of the blend per channel, mixed over the (remapped) image. Use
+///   Mix::uniform(opacity) for a single opacity. None is fully opaque.
+pub fn blend_luminosity(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    rhs: Option<Vec<String>>,
+    color: Rgb<u8>,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    blend(img, lhs, rhs, color, BlendMode::Luminosity, mix)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,7 +810,7 @@ mod tests {
         let red = load_image("ff0000.png".to_string());
         let control_color = get_color_from_control(red.clone());
 
-        let out = overlay(red.clone(), None, None, Rgb([255, 0, 0]));
+        let out = overlay(red.clone(), None, None, Rgb([255, 0, 0]), None);
 
         println!(
             "{:?} == {:?}",
@@ -161,7 +828,7 @@ mod tests {
         let red = load_image("ff0000.png".to_string());
         let control_color = get_color_from_control(red.clone());
 
-        let out = screen(red.clone(), None, None, Rgb([0, 0, 255]));
+        let out = screen(red.clone(), None, None, Rgb([0, 0, 255]), None);
 
         println!(
             "{:?} == {:?}",
@@ -173,4 +840,108 @@ mod tests {
 
         assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
     }
+
+    #[test]
+    fn test_multiply() {
+        let red = load_image("ff0000.png".to_string());
+        let control_color = get_color_from_control(red.clone());
+
+        let out = multiply(red.clone(), None, None, Rgb([128, 0, 255]), None);
+
+        println!(
+            "{:?} == {:?}",
+            control_color,
+            out.get_pixel(0, 0).to_rgb().0
+        );
+
+        const EXPECTED: Rgb<u8> = Rgb([128, 0, 0]);
+
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
+    }
+
+    #[test]
+    fn test_separable_reference_values() {
+        let backdrop =
+            DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba([64, 128, 192, 255])));
+        let source = Rgb([200, 100, 30]);
+
+        let cases: [(BlendMode, [u8; 3]); 17] = [
+            (BlendMode::Multiply, [50, 50, 23]),
+            (BlendMode::Darken, [64, 100, 30]),
+            (BlendMode::Lighten, [200, 128, 192]),
+            (BlendMode::ColorDodge, [255, 211, 218]),
+            (BlendMode::ColorBurn, [11, 0, 0]),
+            (BlendMode::LinearDodge, [255, 228, 222]),
+            (BlendMode::LinearBurn, [9, 0, 0]),
+            (BlendMode::HardLight, [173, 100, 45]),
+            (BlendMode::SoftLight, [100, 114, 156]),
+            (BlendMode::VividLight, [148, 93, 0]),
+            (BlendMode::LinearLight, [209, 73, 0]),
+            (BlendMode::PinLight, [145, 128, 60]),
+            (BlendMode::HardMix, [255, 0, 0]),
+            (BlendMode::Difference, [136, 28, 162]),
+            (BlendMode::Exclusion, [164, 128, 177]),
+            (BlendMode::Subtract, [0, 28, 162]),
+            (BlendMode::Divide, [82, 255, 255]),
+        ];
+
+        for (mode, expected) in cases {
+            let out = blend(backdrop.clone(), None, None, source, mode, None);
+
+            assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb(expected), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_non_separable_reference_values() {
+        let backdrop =
+            DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba([64, 128, 192, 255])));
+        let source = Rgb([200, 100, 30]);
+
+        let out = blend_hue(backdrop.clone(), None, None, source, None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([174, 99, 46]));
+
+        let out = blend_saturation(backdrop.clone(), None, None, source, None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([47, 132, 217]));
+
+        let out = blend_color(backdrop.clone(), None, None, source, None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([194, 94, 24]));
+
+        let out = blend_luminosity(backdrop.clone(), None, None, source, None);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([70, 134, 198]));
+    }
+
+    #[test]
+    fn test_blend_mix() {
+        let backdrop =
+            DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba([64, 128, 192, 255])));
+
+        let out = blend_hue(
+            backdrop.clone(),
+            None,
+            None,
+            Rgb([200, 100, 30]),
+            Some(Mix::uniform(0.5)),
+        );
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([119, 114, 119]));
+
+        let out = blend_hue(
+            backdrop.clone(),
+            None,
+            None,
+            Rgb([200, 100, 30]),
+            Some(Mix::uniform(0.0)),
+        );
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([64, 128, 192]));
+
+        // Mixed over the remapped image, not the input
+        let out = blend_hue(
+            backdrop,
+            Some(vec!["b".to_string(), "g".to_string(), "r".to_string()]),
+            None,
+            Rgb([200, 100, 30]),
+            Some(Mix::uniform(0.0)),
+        );
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([192, 128, 64]));
+    }
 }
@@ -0,0 +1,156 @@
+use crate::utils::{sample_bilinear, Channel, EdgeMode};
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Build the output by sampling each channel at its own source coordinates.
+///
+/// The output alpha is the largest alpha of the three samples, so a pixel only becomes
+/// transparent when every channel was sampled from outside of a transparent edge.
+fn split_channels(
+    img: DynamicImage,
+    lhs: Option<[Channel; 3]>,
+    edge: EdgeMode,
+    source_position: impl Fn(usize, f64, f64) -> (f64, f64) + Sync,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    let channels = lhs.map_or([0, 1, 2], |lhs| lhs.map(Channel::index));
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let mut out = [0u8; 4];
+
+        for (i, channel) in channels.iter().enumerate() {
+            let (sx, sy) = source_position(i, x as f64, y as f64);
+            let sample = sample_bilinear(&img, sx, sy, edge);
+
+            out[i] = sample[*channel].round().clamp(0.0, 255.0) as u8;
+            out[3] = out[3].max(sample[3].round().clamp(0.0, 255.0) as u8);
+        }
+
+        *pixel = Rgba(out);
+    });
+
+    output
+}
+
+/// RGB split. Offsets each channel independently, with sub-pixel offsets sampled bilinearly.
+/// Output channels are remappable, so lhs [B, G, R] shifts the blue channel into red.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional source channel of each output channel.
+/// * `offsets` - (dx, dy) in pixels for the R, G and B channels. Positive moves right and down.
+/// * `edge` - What is sampled past the edges of the image.
+pub fn channel_shift(
+    img: DynamicImage,
+    lhs: Option<[Channel; 3]>,
+    offsets: [(f64, f64); 3],
+    edge: EdgeMode,
+) -> RgbaImage {
+    split_channels(img, lhs, edge, |i, x, y| {
+        (x - offsets[i].0, y - offsets[i].1)
+    })
+}
+
+/// Lens chromatic aberration. Scales each channel radially from a centre, so the channels drift
+/// apart towards the edges of the image.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional source channel of each output channel.
+/// * `center` - Optional centre of the lens in pixels, defaults to the centre of the image.
+/// * `scales` - Scale factor of the R, G and B channels. 1.0 leaves a channel in place, above
+///   1.0 magnifies it. Something like [1.01, 1.0, 0.99] mimics a real lens.
+/// * `edge` - What is sampled past the edges of the image.
+pub fn chromatic_aberration(
+    img: DynamicImage,
+    lhs: Option<[Channel; 3]>,
+    center: Option<(f64, f64)>,
+    scales: [f64; 3],
+    edge: EdgeMode,
+) -> RgbaImage {
+    let (cx, cy) = center.unwrap_or((
+        (img.width() as f64 - 1.0) / 2.0,
+        (img.height() as f64 - 1.0) / 2.0,
+    ));
+
+    split_channels(img, lhs, edge, |i, x, y| {
+        let scale = if scales[i] == 0.0 { 1.0 } else { scales[i] };
+        (cx + (x - cx) / scale, cy + (y - cy) / scale)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_images::gradient;
+
+    #[test]
+    fn test_channel_shift() {
+        let out = channel_shift(
+            gradient(8, 8),
+            None,
+            [(1.0, 0.0), (0.0, 0.0), (0.0, -2.0)],
+            EdgeMode::Clamp,
+        );
+
+        // Red comes from one pixel to the left, blue from two pixels below
+        assert_eq!(*out.get_pixel(4, 4), Rgba([30, 40, 46, 255]));
+
+        // Clamped at the left edge
+        assert_eq!(out.get_pixel(0, 0)[0], 0);
+    }
+
+    #[test]
+    fn test_channel_shift_subpixel() {
+        let out = channel_shift(
+            gradient(8, 8),
+            None,
+            [(0.5, 0.0), (0.0, 0.0), (0.0, 0.0)],
+            EdgeMode::Clamp,
+        );
+
+        assert_eq!(out.get_pixel(4, 4)[0], 35);
+    }
+
+    #[test]
+    fn test_channel_shift_edges() {
+        let offsets = [(2.0, 0.0), (2.0, 0.0), (2.0, 0.0)];
+
+        let out = channel_shift(gradient(8, 8), None, offsets, EdgeMode::Transparent);
+        assert_eq!(*out.get_pixel(0, 3), Rgba([0, 0, 0, 0]));
+
+        let out = channel_shift(gradient(8, 8), None, offsets, EdgeMode::Wrap);
+        assert_eq!(*out.get_pixel(0, 3), Rgba([60, 30, 63, 255]));
+
+        // Half off a transparent edge fades out without darkening
+        let offsets = [(0.5, 0.0); 3];
+        let out = channel_shift(gradient(8, 8), None, offsets, EdgeMode::Transparent);
+        assert_eq!(*out.get_pixel(0, 5), Rgba([0, 50, 5, 128]));
+    }
+
+    #[test]
+    fn test_channel_shift_remap() {
+        let out = channel_shift(
+            gradient(8, 8),
+            Some([Channel::B, Channel::G, Channel::R]),
+            [(0.0, 0.0); 3],
+            EdgeMode::Clamp,
+        );
+
+        assert_eq!(*out.get_pixel(2, 1), Rgba([21, 10, 20, 255]));
+    }
+
+    #[test]
+    fn test_chromatic_aberration() {
+        let out = chromatic_aberration(
+            gradient(8, 8),
+            None,
+            Some((0.0, 0.0)),
+            [2.0, 1.0, 1.0],
+            EdgeMode::Clamp,
+        );
+
+        // Red is magnified 2x from the top left corner, the other channels are untouched
+        assert_eq!(*out.get_pixel(6, 2), Rgba([30, 20, 62, 255]));
+    }
+}
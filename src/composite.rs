@@ -0,0 +1,245 @@
+use std::str::FromStr;
+
+use crate::blend::BlendMode;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Porter-Duff compositing operators. The source is the image being composited, the destination
+/// is the backdrop it is composited onto.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CompositeOp {
+    Clear,
+    Source,
+    Destination,
+    SourceOver,
+    DestinationOver,
+    SourceIn,
+    DestinationIn,
+    SourceOut,
+    DestinationOut,
+    SourceAtop,
+    DestinationAtop,
+    Xor,
+    Plus,
+}
+
+/// Clap FromStr
+impl FromStr for CompositeOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "clear" => Ok(CompositeOp::Clear),
+            "source" | "src" | "copy" => Ok(CompositeOp::Source),
+            "destination" | "dst" => Ok(CompositeOp::Destination),
+            "source-over" | "src-over" | "over" => Ok(CompositeOp::SourceOver),
+            "destination-over" | "dst-over" => Ok(CompositeOp::DestinationOver),
+            "source-in" | "src-in" | "in" => Ok(CompositeOp::SourceIn),
+            "destination-in" | "dst-in" => Ok(CompositeOp::DestinationIn),
+            "source-out" | "src-out" | "out" => Ok(CompositeOp::SourceOut),
+            "destination-out" | "dst-out" => Ok(CompositeOp::DestinationOut),
+            "source-atop" | "src-atop" | "atop" => Ok(CompositeOp::SourceAtop),
+            "destination-atop" | "dst-atop" => Ok(CompositeOp::DestinationAtop),
+            "xor" => Ok(CompositeOp::Xor),
+            "plus" | "lighter" => Ok(CompositeOp::Plus),
+
+            _ => Err(format!("Invalid CompositeOp name: {}", s)),
+        }
+    }
+}
+
+impl CompositeOp {
+    /// The Porter-Duff fractions (Fa, Fb) of the source and destination that make it into the
+    /// output, given the source alpha and destination alpha.
+    pub fn factors(self, alpha_s: f64, alpha_b: f64) -> (f64, f64) {
+        match self {
+            CompositeOp::Clear => (0.0, 0.0),
+            CompositeOp::Source => (1.0, 0.0),
+            CompositeOp::Destination => (0.0, 1.0),
+            CompositeOp::SourceOver => (1.0, 1.0 - alpha_s),
+            CompositeOp::DestinationOver => (1.0 - alpha_b, 1.0),
+            CompositeOp::SourceIn => (alpha_b, 0.0),
+            CompositeOp::DestinationIn => (0.0, alpha_s),
+            CompositeOp::SourceOut => (1.0 - alpha_b, 0.0),
+            CompositeOp::DestinationOut => (0.0, 1.0 - alpha_s),
+            CompositeOp::SourceAtop => (alpha_b, 1.0 - alpha_s),
+            CompositeOp::DestinationAtop => (1.0 - alpha_b, alpha_s),
+            CompositeOp::Xor => (1.0 - alpha_b, 1.0 - alpha_s),
+            CompositeOp::Plus => (1.0, 1.0),
+        }
+    }
+}
+
+/// Composite one image onto another.
+///
+/// Follows the W3C Compositing and Blending model: the source color is first blended with the
+/// backdrop using `mode`, then the result is composited with the Porter-Duff operator `op` on
+/// premultiplied alpha. BlendMode::Normal gives plain Porter-Duff compositing.
+///
+/// The output has the dimensions of the backdrop. Source pixels outside of the backdrop are
+/// ignored, and backdrop pixels not covered by the source are treated as having a fully
+/// transparent source.
+/// * `backdrop` - The image::DynamicImage being composited onto, the destination.
+/// * `source` - The image::DynamicImage being composited.
+/// * `op` - The Porter-Duff operator.
+/// * `mode` - The blend mode applied to the source before compositing.
+/// * `opacity` - 0.0-1.0, multiplies the alpha of the source.
+pub fn composite(
+    backdrop: DynamicImage,
+    source: DynamicImage,
+    op: CompositeOp,
+    mode: BlendMode,
+    opacity: f64,
+) -> RgbaImage {
+    let (width, height) = backdrop.dimensions();
+    let (source_width, source_height) = source.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    let opacity = opacity.clamp(0.0, 1.0);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let b_pixel = backdrop.get_pixel(x, y);
+        let s_pixel = if x < source_width && y < source_height {
+            source.get_pixel(x, y)
+        } else {
+            Rgba([0, 0, 0, 0])
+        };
+
+        *pixel = composite_pixel(b_pixel, s_pixel, op, mode, opacity);
+    });
+
+    output
+}
+
+/// Composite a single source pixel onto a backdrop pixel. Both are straight (not premultiplied)
+/// alpha, as are all images in imgfx.
+pub fn composite_pixel(
+    backdrop: Rgba<u8>,
+    source: Rgba<u8>,
+    op: CompositeOp,
+    mode: BlendMode,
+    opacity: f64,
+) -> Rgba<u8> {
+    let alpha_b = backdrop[3] as f64 / 255.0;
+    let alpha_s = source[3] as f64 / 255.0 * opacity;
+
+    let cb = [0, 1, 2].map(|i| backdrop[i] as f64 / 255.0);
+    let cs = [0, 1, 2].map(|i| source[i] as f64 / 255.0);
+
+    // Cs' = (1 - ab) * Cs + ab * B(Cb, Cs)
+    let blended = mode.blend(cb, cs);
+    let cs = [0, 1, 2].map(|i| (1.0 - alpha_b) * cs[i] + alpha_b * blended[i]);
+
+    let (fa, fb) = op.factors(alpha_s, alpha_b);
+
+    let alpha_o = (fa * alpha_s + fb * alpha_b).min(1.0);
+
+    if alpha_o <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    // Premultiplied co = Fa * as * Cs + Fb * ab * Cb, then back to straight alpha
+    let [r, g, b] = [0, 1, 2].map(|i| {
+        let co = (fa * alpha_s * cs[i] + fb * alpha_b * cb[i]).min(1.0);
+        ((co / alpha_o) * 255.0).round().clamp(0.0, 255.0) as u8
+    });
+
+    let a = (alpha_o * 255.0).round() as u8;
+
+    Rgba([r, g, b, a])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_images::solid;
+
+    #[test]
+    fn test_source_over() {
+        let blue = solid(2, 2, Rgba([0, 0, 255, 255]));
+        let half_red = solid(2, 2, Rgba([255, 0, 0, 128]));
+
+        let out = composite(
+            blue,
+            half_red,
+            CompositeOp::SourceOver,
+            BlendMode::Normal,
+            1.0,
+        );
+
+        assert_eq!(*out.get_pixel(0, 0), Rgba([128, 0, 127, 255]))
+    }
+
+    #[test]
+    fn test_source_over_transparent_backdrop() {
+        let clear = solid(2, 2, Rgba([0, 0, 0, 0]));
+        let half_red = solid(2, 2, Rgba([255, 0, 0, 128]));
+
+        let out = composite(
+            clear,
+            half_red,
+            CompositeOp::SourceOver,
+            BlendMode::Normal,
+            1.0,
+        );
+
+        // Premultiplied maths keeps the straight color intact
+        assert_eq!(*out.get_pixel(0, 0), Rgba([255, 0, 0, 128]))
+    }
+
+    #[test]
+    fn test_screen_over_with_opacity() {
+        let grey = solid(2, 2, Rgba([128, 128, 128, 255]));
+        let red = solid(2, 2, Rgba([255, 0, 0, 255]));
+
+        let out = composite(grey, red, CompositeOp::SourceOver, BlendMode::Screen, 0.6);
+
+        // Screen gives (255, 128, 128), mixed 60% over the grey
+        assert_eq!(*out.get_pixel(0, 0), Rgba([204, 128, 128, 255]))
+    }
+
+    #[test]
+    fn test_xor_and_clear() {
+        let blue = solid(2, 2, Rgba([0, 0, 255, 255]));
+        let red = solid(2, 2, Rgba([255, 0, 0, 255]));
+
+        let out = composite(
+            blue.clone(),
+            red.clone(),
+            CompositeOp::Xor,
+            BlendMode::Normal,
+            1.0,
+        );
+        assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+
+        let out = composite(blue, red, CompositeOp::Clear, BlendMode::Normal, 1.0);
+        assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 0, 0]))
+    }
+
+    #[test]
+    fn test_destination_in() {
+        let blue = solid(2, 2, Rgba([0, 0, 255, 255]));
+        let half_red = solid(2, 2, Rgba([255, 0, 0, 128]));
+
+        let out = composite(
+            blue,
+            half_red,
+            CompositeOp::DestinationIn,
+            BlendMode::Normal,
+            1.0,
+        );
+
+        assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 255, 128]))
+    }
+
+    #[test]
+    fn test_plus() {
+        let blue = solid(2, 2, Rgba([0, 0, 200, 255]));
+        let red = solid(2, 2, Rgba([200, 0, 100, 255]));
+
+        let out = composite(blue, red, CompositeOp::Plus, BlendMode::Normal, 1.0);
+
+        assert_eq!(*out.get_pixel(0, 0), Rgba([200, 0, 255, 255]))
+    }
+}
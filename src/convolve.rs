@@ -0,0 +1,578 @@
+use std::str::FromStr;
+
+use crate::utils::{mix_pixels, EdgeMode, Mix};
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// A convolution kernel with odd dimensions, centred on the middle weight. Like most image
+/// editors the kernel is not flipped, so the weight right of the centre reads the pixel to the
+/// right.
+///
+/// Kernels built with `Kernel::separable` also keep their two 1D factors, so convolving with
+/// them takes two cheap passes instead of one full 2D pass.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<f64>,
+    separable: Option<(Vec<f64>, Vec<f64>)>,
+    /// Added to every channel after convolving, e.g. 128 to centre emboss on mid grey.
+    pub bias: f64,
+}
+
+impl Kernel {
+    /// A kernel from its weights in row-major order.
+    ///
+    /// Fails if a dimension is even or the weights don't fill the kernel.
+    // usize::is_multiple_of needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn new(width: usize, height: usize, weights: Vec<f64>) -> Result<Self, String> {
+        if width % 2 == 0 || height % 2 == 0 {
+            return Err(format!("Kernel size must be odd, got {}x{}", width, height));
+        }
+
+        if weights.len() != width * height {
+            return Err(format!(
+                "A {}x{} kernel needs {} weights, got {}",
+                width,
+                height,
+                width * height,
+                weights.len()
+            ));
+        }
+
+        Ok(Kernel {
+            width,
+            height,
+            weights,
+            separable: None,
+            bias: 0.0,
+        })
+    }
+
+    /// A kernel that is the outer product of a horizontal and a vertical 1D kernel.
+    pub fn separable(horizontal: Vec<f64>, vertical: Vec<f64>) -> Result<Self, String> {
+        let weights = vertical
+            .iter()
+            .flat_map(|v| horizontal.iter().map(move |h| h * v))
+            .collect();
+
+        let mut kernel = Kernel::new(horizontal.len(), vertical.len(), weights)?;
+        kernel.separable = Some((horizontal, vertical));
+
+        Ok(kernel)
+    }
+
+    pub fn with_bias(mut self, bias: f64) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    /// Scale the weights to sum to 1, keeping the brightness of the image. Kernels summing to 0
+    /// are left as is.
+    pub fn normalised(mut self) -> Self {
+        let sum: f64 = self.weights.iter().sum();
+
+        if sum != 0.0 {
+            self.weights.iter_mut().for_each(|w| *w /= sum);
+
+            if let Some((horizontal, vertical)) = &mut self.separable {
+                let h: f64 = horizontal.iter().sum();
+                let v: f64 = vertical.iter().sum();
+                horizontal.iter_mut().for_each(|w| *w /= h);
+                vertical.iter_mut().for_each(|w| *w /= v);
+            }
+        }
+
+        self
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// Mean of a (2 * radius + 1) square.
+    pub fn box_blur(radius: usize) -> Self {
+        let row = vec![1.0; radius * 2 + 1];
+        Kernel::separable(row.clone(), row).unwrap().normalised()
+    }
+
+    /// Gaussian blur, reaching out to 3 sigma.
+    pub fn gaussian(sigma: f64) -> Self {
+        let sigma = sigma.max(f64::EPSILON);
+        let radius = (sigma * 3.0).ceil() as i64;
+
+        let row: Vec<f64> = (-radius..=radius)
+            .map(|x| (-(x * x) as f64 / (2.0 * sigma * sigma)).exp())
+            .collect();
+
+        Kernel::separable(row.clone(), row).unwrap().normalised()
+    }
+
+    pub fn sharpen() -> Self {
+        Kernel::new(3, 3, vec![0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0]).unwrap()
+    }
+
+    /// Emboss lit from the top left, centred on mid grey.
+    pub fn emboss() -> Self {
+        Kernel::new(3, 3, vec![-1.0, -1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 1.0, 1.0])
+            .unwrap()
+            .with_bias(128.0)
+    }
+
+    /// 4-neighbour Laplacian, the second derivative. Flat areas go to 0.
+    pub fn laplacian() -> Self {
+        Kernel::new(3, 3, vec![0.0, 1.0, 0.0, 1.0, -4.0, 1.0, 0.0, 1.0, 0.0]).unwrap()
+    }
+}
+
+/// Gradient operators for edge detection.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EdgeOperator {
+    Sobel,
+    Prewitt,
+    /// Better rotational symmetry than Sobel.
+    Scharr,
+}
+
+/// Clap FromStr
+impl FromStr for EdgeOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sobel" => Ok(EdgeOperator::Sobel),
+            "prewitt" => Ok(EdgeOperator::Prewitt),
+            "scharr" => Ok(EdgeOperator::Scharr),
+
+            _ => Err(format!("Invalid EdgeOperator name: {}", s)),
+        }
+    }
+}
+
+impl EdgeOperator {
+    /// The horizontal and vertical gradient kernels, both separable.
+    pub fn kernels(&self) -> (Kernel, Kernel) {
+        let smooth = match self {
+            EdgeOperator::Sobel => vec![1.0, 2.0, 1.0],
+            EdgeOperator::Prewitt => vec![1.0, 1.0, 1.0],
+            EdgeOperator::Scharr => vec![3.0, 10.0, 3.0],
+        };
+        let derivative = vec![-1.0, 0.0, 1.0];
+
+        (
+            Kernel::separable(derivative.clone(), smooth.clone()).unwrap(),
+            Kernel::separable(smooth, derivative).unwrap(),
+        )
+    }
+}
+
+/// Image pixels as f64, so chained passes don't lose precision.
+type Buffer = Vec<[f64; 4]>;
+
+fn to_buffer(img: &RgbaImage) -> Buffer {
+    img.pixels()
+        .map(|pixel| pixel.0.map(|channel| channel as f64))
+        .collect()
+}
+
+/// Scale the color of a pixel by its alpha, so transparent pixels carry no color.
+fn premultiply(pixel: [f64; 4]) -> [f64; 4] {
+    let alpha = pixel[3] / 255.0;
+    [
+        pixel[0] * alpha,
+        pixel[1] * alpha,
+        pixel[2] * alpha,
+        pixel[3],
+    ]
+}
+
+/// One convolution pass over a buffer, rows in parallel.
+fn convolve_pass(
+    src: &Buffer,
+    width: u32,
+    height: u32,
+    (kernel_width, kernel_height, weights): (usize, usize, &[f64]),
+    edge: EdgeMode,
+    fill: [f64; 4],
+) -> Buffer {
+    if src.is_empty() {
+        return vec![];
+    }
+
+    let (rx, ry) = ((kernel_width / 2) as i64, (kernel_height / 2) as i64);
+
+    let mut output: Buffer = vec![[0.0; 4]; src.len()];
+
+    output
+        .par_chunks_mut(width as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate() {
+                let mut sum = [0.0; 4];
+
+                for ky in 0..kernel_height {
+                    for kx in 0..kernel_width {
+                        let weight = weights[ky * kernel_width + kx];
+                        if weight == 0.0 {
+                            continue;
+                        }
+
+                        let sx = x as i64 + kx as i64 - rx;
+                        let sy = y as i64 + ky as i64 - ry;
+
+                        let sample = match edge.resolve(sx, sy, width, height) {
+                            Some((px, py)) => &src[py as usize * width as usize + px as usize],
+                            None => &fill,
+                        };
+
+                        for c in 0..4 {
+                            sum[c] += sample[c] * weight;
+                        }
+                    }
+                }
+
+                *out = sum;
+            }
+        });
+
+    output
+}
+
+/// Convolve a buffer with a kernel, in two passes when it is separable.
+fn convolve_buffer(
+    src: &Buffer,
+    width: u32,
+    height: u32,
+    kernel: &Kernel,
+    edge: EdgeMode,
+    premultiplied: bool,
+) -> Buffer {
+    let fill = edge.fill().0.map(|channel| channel as f64);
+    let fill = if premultiplied {
+        premultiply(fill)
+    } else {
+        fill
+    };
+
+    match &kernel.separable {
+        Some((horizontal, vertical)) => {
+            let pass = convolve_pass(
+                src,
+                width,
+                height,
+                (horizontal.len(), 1, horizontal),
+                edge,
+                fill,
+            );
+
+            // Rows past the edge never went through the first pass, weight their fill as if
+            // they had
+            let sum: f64 = horizontal.iter().sum();
+            let fill = fill.map(|channel| channel * sum);
+
+            convolve_pass(
+                &pass,
+                width,
+                height,
+                (1, vertical.len(), vertical),
+                edge,
+                fill,
+            )
+        }
+        None => convolve_pass(
+            src,
+            width,
+            height,
+            (kernel.width, kernel.height, &kernel.weights),
+            edge,
+            fill,
+        ),
+    }
+}
+
+fn to_u8(value: f64) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+/// Convolve the image with a kernel.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `kernel` - The kernel, see Kernel for the built-ins.
+/// * `edge` - What is read past the edges of the image.
+/// * `alpha` - If true alpha is convolved too, as blurs need, with the colors premultiplied by
+///   alpha. If false it is passed through, as edge detection and emboss need.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn convolve(
+    img: DynamicImage,
+    kernel: &Kernel,
+    edge: EdgeMode,
+    alpha: bool,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+
+    // Blurring alpha needs premultiplied colors, or transparent pixels bleed their color in
+    let src = to_buffer(&img);
+    let src = if alpha {
+        src.into_iter().map(premultiply).collect()
+    } else {
+        src
+    };
+
+    let result = convolve_buffer(&src, width, height, kernel, edge, alpha);
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let in_pixel = *img.get_pixel(x, y);
+        let mut sum = result[(y * width + x) as usize];
+
+        if alpha && sum[3] > 0.0 {
+            let coverage = sum[3] / 255.0;
+            for channel in sum.iter_mut().take(3) {
+                *channel /= coverage;
+            }
+        }
+
+        let out = Rgba([
+            to_u8(sum[0] + kernel.bias),
+            to_u8(sum[1] + kernel.bias),
+            to_u8(sum[2] + kernel.bias),
+            if alpha { to_u8(sum[3]) } else { in_pixel[3] },
+        ]);
+
+        mix_pixels(in_pixel, out, mix)
+    })
+}
+
+/// Unsharp mask. Sharpens by adding back the difference between the image and a gaussian blur
+/// of it. Alpha is passed through.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `sigma` - Sigma of the blur, the size of the details that get sharpened.
+/// * `amount` - How much of the difference is added, 1.0 doubles the local contrast.
+/// * `threshold` - Differences smaller than this are left alone, to avoid sharpening noise.
+/// * `edge` - What is read past the edges of the image.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn unsharp_mask(
+    img: DynamicImage,
+    sigma: f64,
+    amount: f64,
+    threshold: u8,
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+
+    let blurred = convolve_buffer(
+        &to_buffer(&img),
+        width,
+        height,
+        &Kernel::gaussian(sigma),
+        edge,
+        false,
+    );
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let in_pixel = *img.get_pixel(x, y);
+        let blur = blurred[(y * width + x) as usize];
+
+        let channel = |c: usize| {
+            let difference = in_pixel[c] as f64 - blur[c];
+            if difference.abs() < threshold as f64 {
+                in_pixel[c]
+            } else {
+                to_u8(in_pixel[c] as f64 + difference * amount)
+            }
+        };
+
+        mix_pixels(
+            in_pixel,
+            Rgba([channel(0), channel(1), channel(2), in_pixel[3]]),
+            mix,
+        )
+    })
+}
+
+/// Edge detection. Each channel becomes the magnitude of its gradient. Alpha is passed through.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `operator` - The gradient kernels, see EdgeOperator.
+/// * `edge` - What is read past the edges of the image.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn edge_detect(
+    img: DynamicImage,
+    operator: EdgeOperator,
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+
+    let src = to_buffer(&img);
+    let (kernel_x, kernel_y) = operator.kernels();
+
+    let gx = convolve_buffer(&src, width, height, &kernel_x, edge, false);
+    let gy = convolve_buffer(&src, width, height, &kernel_y, edge, false);
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let in_pixel = *img.get_pixel(x, y);
+        let i = (y * width + x) as usize;
+
+        let magnitude = |c: usize| to_u8((gx[i][c] * gx[i][c] + gy[i][c] * gy[i][c]).sqrt());
+
+        mix_pixels(
+            in_pixel,
+            Rgba([magnitude(0), magnitude(1), magnitude(2), in_pixel[3]]),
+            mix,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_images::{gradient, solid};
+
+    #[test]
+    fn test_identity() {
+        let kernel = Kernel::new(3, 1, vec![0.0, 1.0, 0.0]).unwrap();
+
+        let out = convolve(gradient(7, 6), &kernel, EdgeMode::Clamp, true, None);
+        assert_eq!(out, gradient(7, 6).to_rgba8());
+
+        assert!(Kernel::new(2, 1, vec![0.5, 0.5]).is_err());
+        assert!(Kernel::new(3, 3, vec![1.0]).is_err());
+    }
+
+    #[test]
+    fn test_separable_matches_full() {
+        let separable =
+            Kernel::separable(vec![1.0, 2.0, 1.0], vec![0.1, 0.0, -0.1, 0.2, 0.1]).unwrap();
+        let full = Kernel::new(3, 5, separable.weights().to_vec()).unwrap();
+
+        for edge in [
+            EdgeMode::Clamp,
+            EdgeMode::Wrap,
+            EdgeMode::Mirror,
+            EdgeMode::Constant(Rgba([255, 0, 0, 255])),
+        ] {
+            let a = convolve(gradient(7, 6), &separable, edge, true, None);
+            let b = convolve(gradient(7, 6), &full, edge, true, None);
+
+            // The float sums only differ in order, which can tip a value ending in .5 either way
+            for (a, b) in a.pixels().zip(b.pixels()) {
+                for c in 0..4 {
+                    assert!(a[c].abs_diff(b[c]) <= 1, "{:?} != {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_edge_modes() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(3, 1, |x, _| {
+            Rgba([[10, 20, 90][x as usize], 0, 0, 255])
+        }));
+        // Reads the pixel one to the left
+        let kernel = Kernel::new(3, 1, vec![1.0, 0.0, 0.0]).unwrap();
+        let left = |edge| convolve(img.clone(), &kernel, edge, false, None).get_pixel(0, 0)[0];
+
+        assert_eq!(left(EdgeMode::Clamp), 10);
+        assert_eq!(left(EdgeMode::Wrap), 90);
+        assert_eq!(left(EdgeMode::Mirror), 20);
+        assert_eq!(left(EdgeMode::Constant(Rgba([50, 0, 0, 255]))), 50);
+        assert_eq!(left(EdgeMode::Transparent), 0);
+    }
+
+    #[test]
+    fn test_blurs_keep_flat_areas() {
+        let img = solid(5, 5, Rgba([100, 150, 200, 255]));
+
+        for kernel in [Kernel::box_blur(2), Kernel::gaussian(1.5)] {
+            let out = convolve(img.clone(), &kernel, EdgeMode::Clamp, true, None);
+            assert_eq!(out, img.to_rgba8());
+        }
+
+        let out = unsharp_mask(img.clone(), 2.0, 1.5, 0, EdgeMode::Mirror, None);
+        assert_eq!(out, img.to_rgba8());
+    }
+
+    #[test]
+    fn test_blur_premultiplies_alpha() {
+        // The color of a fully transparent pixel must not bleed into its neighbours
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(3, 1, |x, _| {
+            if x == 0 {
+                Rgba([255, 0, 0, 0])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        }));
+
+        let out = convolve(img, &Kernel::box_blur(1), EdgeMode::Clamp, true, None);
+        assert_eq!(*out.get_pixel(1, 0), Rgba([0, 0, 255, 170]));
+    }
+
+    #[test]
+    fn test_flat_responses() {
+        let img = solid(5, 5, Rgba([100, 150, 200, 40]));
+
+        let out = convolve(
+            img.clone(),
+            &Kernel::laplacian(),
+            EdgeMode::Clamp,
+            false,
+            None,
+        );
+        assert_eq!(*out.get_pixel(2, 2), Rgba([0, 0, 0, 40]));
+
+        let out = convolve(img.clone(), &Kernel::emboss(), EdgeMode::Clamp, false, None);
+        assert_eq!(*out.get_pixel(2, 2), Rgba([128, 128, 128, 40]));
+
+        let out = convolve(
+            img.clone(),
+            &Kernel::sharpen(),
+            EdgeMode::Clamp,
+            false,
+            None,
+        );
+        assert_eq!(out, img.to_rgba8());
+    }
+
+    #[test]
+    fn test_edge_detect() {
+        // Vertical edge between x = 2 and x = 3
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(6, 5, |x, _| {
+            let v = if x < 3 { 0 } else { 10 };
+            Rgba([v, v, v, 255])
+        }));
+
+        let out = edge_detect(img.clone(), EdgeOperator::Sobel, EdgeMode::Clamp, None);
+        assert_eq!(out.get_pixel(0, 2)[0], 0);
+        assert_eq!(out.get_pixel(2, 2)[0], 40);
+        assert_eq!(out.get_pixel(3, 2)[0], 40);
+
+        let out = edge_detect(img.clone(), EdgeOperator::Prewitt, EdgeMode::Clamp, None);
+        assert_eq!(out.get_pixel(2, 2)[0], 30);
+
+        let out = edge_detect(img, "scharr".parse().unwrap(), EdgeMode::Clamp, None);
+        assert_eq!(out.get_pixel(2, 2)[0], 160);
+    }
+
+    #[test]
+    fn test_unsharp_threshold() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(6, 1, |x, _| {
+            let v = if x < 3 { 100 } else { 110 };
+            Rgba([v, v, v, 255])
+        }));
+
+        let out = unsharp_mask(img.clone(), 1.0, 1.0, 0, EdgeMode::Clamp, None);
+        assert!(out.get_pixel(2, 0)[0] < 100);
+        assert!(out.get_pixel(3, 0)[0] > 110);
+
+        let out = unsharp_mask(img.clone(), 1.0, 1.0, 20, EdgeMode::Clamp, None);
+        assert_eq!(out, img.to_rgba8());
+    }
+}
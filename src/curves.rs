@@ -0,0 +1,342 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::utils::{apply_luts, ChannelLuts, Mix};
+use image::{DynamicImage, RgbaImage};
+
+/// A tone curve through control points, interpolated with a monotone cubic spline
+/// (Fritsch-Carlson). Between two points the curve never overshoots, so an increasing set of
+/// points always gives an increasing curve. Past the first and last points the curve is flat.
+///
+/// Serialises to and from "x,y x,y ...", e.g. "0,0 64,50 255,255".
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve {
+    points: Vec<(u8, u8)>,
+}
+
+impl Curve {
+    /// The straight line from 0,0 to 255,255.
+    pub fn identity() -> Self {
+        Curve {
+            points: vec![(0, 0), (255, 255)],
+        }
+    }
+
+    /// Build a curve from control points, in any order.
+    ///
+    /// Fails if there are no points or two points share the same input value.
+    pub fn new(points: Vec<(u8, u8)>) -> Result<Self, String> {
+        let mut points = points;
+        points.sort_by_key(|point| point.0);
+
+        if points.is_empty() {
+            return Err("Curve has no control points".to_string());
+        }
+
+        if let Some(pair) = points.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(format!("Curve has two control points at x={}", pair[0].0));
+        }
+
+        Ok(Curve { points })
+    }
+
+    pub fn points(&self) -> &[(u8, u8)] {
+        &self.points
+    }
+
+    /// Tangent at every control point, limited so the spline stays monotone.
+    fn tangents(&self) -> Vec<f64> {
+        let points: Vec<(f64, f64)> = self
+            .points
+            .iter()
+            .map(|(x, y)| (*x as f64, *y as f64))
+            .collect();
+        let n = points.len();
+
+        if n < 2 {
+            return vec![0.0; n];
+        }
+
+        let secants: Vec<f64> = points
+            .windows(2)
+            .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+            .collect();
+
+        let mut tangents = vec![0.0; n];
+        tangents[0] = secants[0];
+        tangents[n - 1] = secants[n - 2];
+        for k in 1..n - 1 {
+            if secants[k - 1] * secants[k] > 0.0 {
+                tangents[k] = (secants[k - 1] + secants[k]) / 2.0;
+            }
+        }
+
+        for (k, secant) in secants.iter().enumerate() {
+            if *secant == 0.0 {
+                tangents[k] = 0.0;
+                tangents[k + 1] = 0.0;
+                continue;
+            }
+
+            let a = tangents[k] / secant;
+            let b = tangents[k + 1] / secant;
+            let length = a * a + b * b;
+
+            if length > 9.0 {
+                let t = 3.0 / length.sqrt();
+                tangents[k] = t * a * secant;
+                tangents[k + 1] = t * b * secant;
+            }
+        }
+
+        tangents
+    }
+
+    /// Bake the curve into a lookup table.
+    pub fn lut(&self) -> [u8; 256] {
+        let tangents = self.tangents();
+        let points = &self.points;
+        let (first, last) = (points[0], points[points.len() - 1]);
+
+        std::array::from_fn(|v| {
+            if v <= first.0 as usize {
+                return first.1;
+            }
+            if v >= last.0 as usize {
+                return last.1;
+            }
+
+            let k = points.partition_point(|point| (point.0 as usize) <= v) - 1;
+
+            let (x0, y0) = (points[k].0 as f64, points[k].1 as f64);
+            let (x1, y1) = (points[k + 1].0 as f64, points[k + 1].1 as f64);
+            let h = x1 - x0;
+            let t = (v as f64 - x0) / h;
+
+            // Cubic Hermite basis
+            let t2 = t * t;
+            let t3 = t2 * t;
+            let y = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                + (t3 - 2.0 * t2 + t) * h * tangents[k]
+                + (-2.0 * t3 + 3.0 * t2) * y1
+                + (t3 - t2) * h * tangents[k + 1];
+
+            y.round().clamp(0.0, 255.0) as u8
+        })
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.lut()
+            .iter()
+            .enumerate()
+            .all(|(v, out)| v == *out as usize)
+    }
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Curve::identity()
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let points: Vec<String> = self
+            .points
+            .iter()
+            .map(|(x, y)| format!("{},{}", x, y))
+            .collect();
+
+        write!(f, "{}", points.join(" "))
+    }
+}
+
+/// Clap FromStr, control points as "x,y x,y ...".
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let points = s
+            .split_whitespace()
+            .map(|point| {
+                let (x, y) = point
+                    .split_once(',')
+                    .ok_or(format!("Invalid curve point: {}", point))?;
+
+                match (x.parse::<u8>(), y.parse::<u8>()) {
+                    (Ok(x), Ok(y)) => Ok((x, y)),
+                    _ => Err(format!("Invalid curve point: {}", point)),
+                }
+            })
+            .collect::<Result<Vec<(u8, u8)>, String>>()?;
+
+        Curve::new(points)
+    }
+}
+
+/// A set of tone curves. Each channel goes through its own curve first and then through the
+/// master curve.
+///
+/// Serialises to and from "channel=points" entries separated by ";", e.g.
+/// "master=0,0 128,150 255,255; b=0,20 255,255". Channels left out are identity curves.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Curves {
+    pub master: Curve,
+    pub r: Curve,
+    pub g: Curve,
+    pub b: Curve,
+}
+
+impl Curves {
+    /// The same curve on all three channels.
+    pub fn master(curve: Curve) -> Self {
+        Curves {
+            master: curve,
+            ..Curves::default()
+        }
+    }
+
+    /// Bake the curves into lookup tables, with the master curve folded into each channel.
+    pub fn luts(&self) -> ChannelLuts {
+        let master = self.master.lut();
+
+        [&self.r, &self.g, &self.b].map(|curve| {
+            let channel = curve.lut();
+            std::array::from_fn(|v| master[channel[v] as usize])
+        })
+    }
+}
+
+impl fmt::Display for Curves {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = [
+            ("master", &self.master),
+            ("r", &self.r),
+            ("g", &self.g),
+            ("b", &self.b),
+        ]
+        .iter()
+        .filter(|(_, curve)| **curve != Curve::identity())
+        .map(|(name, curve)| format!("{}={}", name, curve))
+        .collect();
+
+        write!(f, "{}", entries.join("; "))
+    }
+}
+
+/// Clap FromStr, "channel=points" entries separated by ";".
+impl FromStr for Curves {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut curves = Curves::default();
+
+        for entry in s.split(';').map(|entry| entry.trim()) {
+            if entry.is_empty() {
+                continue;
+            }
+
+            let (name, points) = entry
+                .split_once('=')
+                .ok_or(format!("Invalid curves entry: {}", entry))?;
+
+            let curve = points.parse::<Curve>()?;
+
+            match name.trim().to_lowercase().as_str() {
+                "master" | "rgb" => curves.master = curve,
+                "r" => curves.r = curve,
+                "g" => curves.g = curve,
+                "b" => curves.b = curve,
+
+                _ => return Err(format!("Invalid curves channel: {}", name)),
+            }
+        }
+
+        Ok(curves)
+    }
+}
+
+/// Tone curves, the per channel and master curve adjustment of photo editors.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings choosing the source channel of each output channel.
+/// * `curves` - The master and per channel curves.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn curves(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    curves: &Curves,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    apply_luts(img, lhs, &curves.luts(), mix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    #[test]
+    fn test_identity() {
+        assert!(Curve::identity().is_identity());
+        assert!(Curve::new(vec![(0, 0), (128, 128), (255, 255)])
+            .unwrap()
+            .is_identity());
+    }
+
+    #[test]
+    fn test_passes_through_points() {
+        let curve = Curve::new(vec![(255, 240), (0, 10), (64, 100), (192, 200)]).unwrap();
+        let lut = curve.lut();
+
+        for (x, y) in curve.points() {
+            assert_eq!(lut[*x as usize], *y);
+        }
+    }
+
+    #[test]
+    fn test_monotone() {
+        // A steep step that an ordinary cubic spline would overshoot
+        let curve: Curve = "0,0 100,10 110,240 255,255".parse().unwrap();
+        let lut = curve.lut();
+
+        assert!(lut.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // Flat outside of the points
+        let curve: Curve = "50,20 200,220".parse().unwrap();
+        let lut = curve.lut();
+        assert_eq!((lut[0], lut[50], lut[200], lut[255]), (20, 20, 220, 220));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Curve::new(vec![]).is_err());
+        assert!("0,0 0,10".parse::<Curve>().is_err());
+        assert!("0,0 256,10".parse::<Curve>().is_err());
+        assert!("x=0,0 255,255".parse::<Curves>().is_err());
+    }
+
+    #[test]
+    fn test_serialise_round_trip() {
+        let text = "master=0,0 128,150 255,255; b=0,20 255,255";
+
+        let parsed: Curves = text.parse().unwrap();
+        assert_eq!(parsed.r, Curve::identity());
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.to_string().parse::<Curves>().unwrap(), parsed);
+    }
+
+    #[test]
+    fn test_curves() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba([0, 128, 255, 77])));
+
+        // Invert blue, then lift everything by the master curve
+        let adjustment = Curves {
+            master: "0,50 255,255".parse().unwrap(),
+            b: "0,255 255,0".parse().unwrap(),
+            ..Curves::default()
+        };
+
+        let out = curves(img, None, &adjustment, None);
+        assert_eq!(*out.get_pixel(0, 0), Rgba([50, 153, 50, 77]));
+    }
+}
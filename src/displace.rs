@@ -0,0 +1,270 @@
+use crate::utils::{mix_pixels, sample_bilinear, Channel, EdgeMode, Mix, Rng};
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Where the displacement amounts are read from.
+#[derive(Clone, Debug)]
+pub enum DisplacementSource {
+    /// A displacement map, stretched over the image if the sizes differ.
+    Image(DynamicImage),
+    /// Smooth value noise with an independent field in each of the R, G and B channels.
+    /// `scale` is the size of the largest features in pixels, each extra octave adds detail at
+    /// half the size and half the strength.
+    Noise { scale: f64, octaves: u32, seed: u64 },
+}
+
+impl DisplacementSource {
+    /// Render the source at the size of the image being displaced.
+    fn render(self, width: u32, height: u32) -> RgbaImage {
+        match self {
+            DisplacementSource::Image(map) => {
+                let map = map.into_rgba8();
+                if map.dimensions() == (width, height) {
+                    return map;
+                }
+
+                let (sx, sy) = (
+                    map.width() as f64 / width as f64,
+                    map.height() as f64 / height as f64,
+                );
+
+                let mut output: RgbaImage = ImageBuffer::new(width, height);
+                output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+                    let sample = sample_bilinear(
+                        &map,
+                        (x as f64 + 0.5) * sx - 0.5,
+                        (y as f64 + 0.5) * sy - 0.5,
+                        EdgeMode::Clamp,
+                    );
+                    *pixel = Rgba(sample.map(|channel| channel.round().clamp(0.0, 255.0) as u8));
+                });
+
+                output
+            }
+            DisplacementSource::Noise {
+                scale,
+                octaves,
+                seed,
+            } => value_noise(width, height, scale, octaves, seed),
+        }
+    }
+}
+
+/// Fractal value noise in the R, G and B channels, opaque.
+fn value_noise(width: u32, height: u32, scale: f64, octaves: u32, seed: u64) -> RgbaImage {
+    let mut rng = Rng::new(seed);
+
+    // One lattice of random values per octave and channel
+    let mut layers: Vec<(f64, f64, usize, Vec<[f64; 3]>)> = vec![];
+    for octave in 0..octaves.max(1) {
+        let size = (scale / 2f64.powi(octave as i32)).max(1.0);
+        let amplitude = 0.5f64.powi(octave as i32);
+        let columns = (width as f64 / size).ceil() as usize + 2;
+        let rows = (height as f64 / size).ceil() as usize + 2;
+
+        let lattice = (0..columns * rows)
+            .map(|_| [rng.next_f64(), rng.next_f64(), rng.next_f64()])
+            .collect();
+
+        layers.push((size, amplitude, columns, lattice));
+
+        // Finer octaves can't add detail below a pixel
+        if size <= 1.0 {
+            break;
+        }
+    }
+
+    let total: f64 = layers.iter().map(|layer| layer.1).sum();
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let mut value = [0.0; 3];
+
+        for (size, amplitude, columns, lattice) in layers.iter() {
+            let (u, v) = (x as f64 / size, y as f64 / size);
+            let (cx, cy) = (u.floor() as usize, v.floor() as usize);
+            let (fx, fy) = (smooth(u.fract()), smooth(v.fract()));
+
+            let at = |dx: usize, dy: usize| lattice[(cy + dy) * columns + cx + dx];
+            let (tl, tr, bl, br) = (at(0, 0), at(1, 0), at(0, 1), at(1, 1));
+
+            for (c, channel) in value.iter_mut().enumerate() {
+                let top = tl[c] + (tr[c] - tl[c]) * fx;
+                let bottom = bl[c] + (br[c] - bl[c]) * fx;
+                *channel += (top + (bottom - top) * fy) * amplitude;
+            }
+        }
+
+        let [r, g, b] = value.map(|channel| (channel / total * 255.0).round() as u8);
+        *pixel = Rgba([r, g, b, 255]);
+    });
+
+    output
+}
+
+/// Displacement map warp. Each output pixel is sampled bilinearly from the input at an offset
+/// read from the source, so a value of 128 leaves the pixel in place, 255 samples half of
+/// `scale` pixels to the right or below and 0 half of `scale` to the left or above.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `source` - The displacement map or noise field the offsets are read from.
+/// * `channels` - Optional source channels of the x and y offsets. Defaults to [R, G].
+/// * `scale` - (x, y) displacement in pixels across the full range of the channel. A negative
+///   scale reverses the direction, 0.0 disables an axis.
+/// * `edge` - What is sampled past the edges of the image.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn displace(
+    img: DynamicImage,
+    source: DisplacementSource,
+    channels: Option<[Channel; 2]>,
+    scale: (f64, f64),
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+
+    let map = source.render(width, height);
+
+    let channels = channels.map_or([0, 1], |channels| channels.map(Channel::index));
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = *img.get_pixel(x, y);
+        let amounts = map.get_pixel(x, y);
+
+        let dx = (amounts[channels[0]] as f64 - 128.0) / 255.0 * scale.0;
+        let dy = (amounts[channels[1]] as f64 - 128.0) / 255.0 * scale.1;
+
+        let sample = sample_bilinear(&img, x as f64 + dx, y as f64 + dy, edge);
+        let out = Rgba(sample.map(|channel| channel.round().clamp(0.0, 255.0) as u8));
+
+        *pixel = mix_pixels(in_pixel, out, mix);
+    });
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_images::{gradient, solid};
+
+    fn flat_map(color: [u8; 4]) -> DisplacementSource {
+        DisplacementSource::Image(solid(4, 4, Rgba(color)))
+    }
+
+    #[test]
+    fn test_neutral_map() {
+        let img = gradient(16, 16);
+        let out = displace(
+            img.clone(),
+            flat_map([128, 128, 128, 255]),
+            None,
+            (40.0, 40.0),
+            EdgeMode::Clamp,
+            None,
+        );
+
+        assert_eq!(out, img.to_rgba8());
+    }
+
+    #[test]
+    fn test_displace_channels() {
+        // 255 is 127 above neutral and 0 is 128 below, scaled to offsets of exactly 4 pixels
+        let source = || flat_map([255, 128, 0, 255]);
+        let scale = (255.0 / 127.0 * 4.0, 8.0);
+
+        let out = displace(
+            gradient(16, 16),
+            source(),
+            None,
+            scale,
+            EdgeMode::Clamp,
+            None,
+        );
+        assert_eq!(*out.get_pixel(5, 5), Rgba([90, 50, 95, 255]));
+
+        let channels = Some([Channel::G, Channel::B]);
+        let scale = (8.0, 255.0 / 128.0 * 4.0);
+        let out = displace(
+            gradient(16, 16),
+            source(),
+            channels,
+            scale,
+            EdgeMode::Clamp,
+            None,
+        );
+        assert_eq!(*out.get_pixel(5, 5), Rgba([50, 10, 51, 255]));
+    }
+
+    #[test]
+    fn test_displace_subpixel_and_edges() {
+        let out = displace(
+            gradient(16, 16),
+            flat_map([192, 128, 128, 255]),
+            None,
+            (2.0, 0.0),
+            EdgeMode::Clamp,
+            None,
+        );
+        assert_eq!(out.get_pixel(5, 5)[0], 55);
+        assert_eq!(out.get_pixel(15, 5)[0], 150);
+
+        let out = displace(
+            gradient(16, 16),
+            flat_map([192, 128, 128, 255]),
+            None,
+            (2.0, 0.0),
+            EdgeMode::Transparent,
+            None,
+        );
+        assert_eq!(out.get_pixel(15, 5)[3], 127);
+    }
+
+    #[test]
+    fn test_noise_source() {
+        let noise = |seed| {
+            DisplacementSource::Noise {
+                scale: 6.0,
+                octaves: 3,
+                seed,
+            }
+            .render(32, 32)
+        };
+
+        assert_eq!(noise(1), noise(1));
+        assert_ne!(noise(1), noise(2));
+
+        // Octaves past single pixel detail are skipped instead of allocated
+        let many = DisplacementSource::Noise {
+            scale: 6.0,
+            octaves: u32::MAX,
+            seed: 1,
+        };
+        assert_eq!(many.render(32, 32).dimensions(), (32, 32));
+
+        // Smooth, neighbouring pixels are close
+        let field = noise(1);
+        for (x, y, pixel) in field.enumerate_pixels().filter(|(x, _, _)| *x < 31) {
+            let next = field.get_pixel(x + 1, y);
+            assert!((pixel[0] as i32 - next[0] as i32).abs() < 64);
+        }
+
+        let out = displace(
+            gradient(16, 16),
+            DisplacementSource::Noise {
+                scale: 6.0,
+                octaves: 2,
+                seed: 1,
+            },
+            None,
+            (6.0, 6.0),
+            EdgeMode::Mirror,
+            None,
+        );
+        assert_ne!(out, gradient(16, 16).to_rgba8());
+    }
+}
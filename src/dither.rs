@@ -0,0 +1,588 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::palette::{Palette, PaletteMapper};
+use crate::utils::Rng;
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Dithering algorithms. The error diffusion methods push each pixel's quantization error onto
+/// its unprocessed neighbours, the ordered methods add a fixed threshold pattern before
+/// quantizing.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DitherMethod {
+    FloydSteinberg,
+    /// Only diffuses 3/4 of the error, giving lighter, higher contrast results.
+    Atkinson,
+    JarvisJudiceNinke,
+    Sierra,
+    SierraTwoRow,
+    SierraLite,
+    Bayer2,
+    Bayer4,
+    Bayer8,
+    /// Ordered dithering with a 64x64 void-and-cluster blue noise tile.
+    BlueNoise,
+}
+
+/// Clap FromStr
+impl FromStr for DitherMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['-', '_'], "").as_str() {
+            "floydsteinberg" => Ok(DitherMethod::FloydSteinberg),
+            "atkinson" => Ok(DitherMethod::Atkinson),
+            "jarvisjudiceninke" | "jjn" => Ok(DitherMethod::JarvisJudiceNinke),
+            "sierra" => Ok(DitherMethod::Sierra),
+            "sierratworow" => Ok(DitherMethod::SierraTwoRow),
+            "sierralite" => Ok(DitherMethod::SierraLite),
+            "bayer2" => Ok(DitherMethod::Bayer2),
+            "bayer4" => Ok(DitherMethod::Bayer4),
+            "bayer8" => Ok(DitherMethod::Bayer8),
+            "bluenoise" => Ok(DitherMethod::BlueNoise),
+
+            _ => Err(format!("Invalid DitherMethod name: {}", s)),
+        }
+    }
+}
+
+/// Error diffusion kernel as (dx, dy, weight) entries and the divisor of the weights.
+type Kernel = (&'static [(i32, i32, f32)], f32);
+
+impl DitherMethod {
+    /// Error diffusion kernel, or None for the ordered methods.
+    fn kernel(&self) -> Option<Kernel> {
+        match self {
+            DitherMethod::FloydSteinberg => {
+                Some((&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0))
+            }
+            DitherMethod::Atkinson => Some((
+                &[
+                    (1, 0, 1.0),
+                    (2, 0, 1.0),
+                    (-1, 1, 1.0),
+                    (0, 1, 1.0),
+                    (1, 1, 1.0),
+                    (0, 2, 1.0),
+                ],
+                8.0,
+            )),
+            DitherMethod::JarvisJudiceNinke => Some((
+                &[
+                    (1, 0, 7.0),
+                    (2, 0, 5.0),
+                    (-2, 1, 3.0),
+                    (-1, 1, 5.0),
+                    (0, 1, 7.0),
+                    (1, 1, 5.0),
+                    (2, 1, 3.0),
+                    (-2, 2, 1.0),
+                    (-1, 2, 3.0),
+                    (0, 2, 5.0),
+                    (1, 2, 3.0),
+                    (2, 2, 1.0),
+                ],
+                48.0,
+            )),
+            DitherMethod::Sierra => Some((
+                &[
+                    (1, 0, 5.0),
+                    (2, 0, 3.0),
+                    (-2, 1, 2.0),
+                    (-1, 1, 4.0),
+                    (0, 1, 5.0),
+                    (1, 1, 4.0),
+                    (2, 1, 2.0),
+                    (-1, 2, 2.0),
+                    (0, 2, 3.0),
+                    (1, 2, 2.0),
+                ],
+                32.0,
+            )),
+            DitherMethod::SierraTwoRow => Some((
+                &[
+                    (1, 0, 4.0),
+                    (2, 0, 3.0),
+                    (-2, 1, 1.0),
+                    (-1, 1, 2.0),
+                    (0, 1, 3.0),
+                    (1, 1, 2.0),
+                    (2, 1, 1.0),
+                ],
+                16.0,
+            )),
+            DitherMethod::SierraLite => Some((&[(1, 0, 2.0), (-1, 1, 1.0), (0, 1, 1.0)], 4.0)),
+            _ => None,
+        }
+    }
+}
+
+/// What the dither quantizes to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DitherTarget {
+    /// Evenly spaced levels with the given number of bits (1-8) for R, G and B.
+    Depth([u8; 3]),
+    /// The nearest color of a palette.
+    Palette(Palette),
+}
+
+/// Quantizes colors to a DitherTarget.
+enum Quantizer {
+    Depth([f32; 3]),
+    Palette(PaletteMapper),
+}
+
+impl Quantizer {
+    fn new(target: &DitherTarget) -> Self {
+        match target {
+            DitherTarget::Depth(bits) => Quantizer::Depth(bits.map(|bits| {
+                assert!((1..=8).contains(&bits), "Invalid bit depth: {}", bits);
+                ((1u32 << bits) - 1) as f32
+            })),
+            DitherTarget::Palette(palette) => Quantizer::Palette(PaletteMapper::new(palette)),
+        }
+    }
+
+    fn quantize(&self, color: [f32; 3]) -> [u8; 3] {
+        match self {
+            Quantizer::Depth(levels) => [0, 1, 2].map(|i| {
+                let level = (color[i] / 255.0 * levels[i]).round().clamp(0.0, levels[i]);
+                (level * 255.0 / levels[i]).round() as u8
+            }),
+            Quantizer::Palette(mapper) => {
+                let color = color.map(|channel| channel.round().clamp(0.0, 255.0) as u8);
+                mapper.nearest(Rgb(color)).0
+            }
+        }
+    }
+
+    /// How far apart the quantized values are per channel, which is how much an ordered pattern
+    /// needs to nudge a color to move it between them.
+    fn spread(&self, palette: Option<&Palette>) -> [f32; 3] {
+        match (self, palette) {
+            (Quantizer::Depth(levels), _) => levels.map(|levels| 255.0 / levels),
+            (Quantizer::Palette(_), Some(palette)) => [palette_spread(palette); 3],
+            _ => [0.0; 3],
+        }
+    }
+}
+
+/// Average distance from each palette color to its closest other color, capped to the full
+/// channel range.
+fn palette_spread(palette: &Palette) -> f32 {
+    let colors = &palette.colors;
+    if colors.len() < 2 {
+        return 0.0;
+    }
+
+    let distance = |a: &Rgb<u8>, b: &Rgb<u8>| -> f32 {
+        (0..3)
+            .map(|i| (a.0[i] as f32 - b.0[i] as f32).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    };
+
+    let total: f32 = colors
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            colors
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, b)| distance(a, b))
+                .fold(f32::INFINITY, f32::min)
+        })
+        .sum();
+
+    (total / colors.len() as f32).min(255.0)
+}
+
+/// Bayer index matrix of the given size, a power of two, built recursively from the 2x2 matrix.
+fn bayer_matrix(size: usize) -> Vec<u32> {
+    let mut matrix = vec![0u32];
+    let mut n = 1;
+
+    while n < size {
+        let mut next = vec![0u32; n * n * 4];
+        for y in 0..n {
+            for x in 0..n {
+                let value = matrix[y * n + x] * 4;
+                next[y * 2 * n + x] = value;
+                next[y * 2 * n + x + n] = value + 2;
+                next[(y + n) * 2 * n + x] = value + 3;
+                next[(y + n) * 2 * n + x + n] = value + 1;
+            }
+        }
+        matrix = next;
+        n *= 2;
+    }
+
+    matrix
+}
+
+/// Side of the blue noise tile.
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Blue noise ranks generated with the void-and-cluster method (Ulichney 1993), computed once.
+///
+/// After the initial pattern is relaxed and ranked, the remaining pixels are filled into the
+/// largest void one by one rather than by the inverted tightest-cluster step of the paper, which
+/// gives a visually equivalent tile.
+fn blue_noise() -> &'static [u32] {
+    static TILE: OnceLock<Vec<u32>> = OnceLock::new();
+
+    TILE.get_or_init(|| {
+        const N: usize = BLUE_NOISE_SIZE;
+        const SIGMA: f32 = 1.5;
+
+        // Gaussian energy contributed by a pixel at a toroidal offset
+        let falloff: Vec<f32> = (0..N * N)
+            .map(|i| {
+                let (dx, dy) = (i % N, i / N);
+                let dx = dx.min(N - dx) as f32;
+                let dy = dy.min(N - dy) as f32;
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            })
+            .collect();
+
+        let update = |energy: &mut [f32], index: usize, sign: f32| {
+            let (px, py) = (index % N, index / N);
+            for (i, value) in energy.iter_mut().enumerate() {
+                let (x, y) = (i % N, i / N);
+                let offset = (y + N - py) % N * N + (x + N - px) % N;
+                *value += sign * falloff[offset];
+            }
+        };
+
+        let find = |energy: &[f32], pattern: &[bool], set: bool, tightest: bool| -> usize {
+            let mut best = usize::MAX;
+            for i in 0..N * N {
+                if pattern[i] != set {
+                    continue;
+                }
+                if best == usize::MAX
+                    || (tightest && energy[i] > energy[best])
+                    || (!tightest && energy[i] < energy[best])
+                {
+                    best = i;
+                }
+            }
+            best
+        };
+
+        // Initial random pattern with 10% of the pixels set
+        let mut rng = Rng::new(0x5EED);
+        let mut pattern = vec![false; N * N];
+        let mut energy = vec![0.0f32; N * N];
+        let mut ones = 0;
+        while ones < N * N / 10 {
+            let index = rng.below((N * N) as u64) as usize;
+            if !pattern[index] {
+                pattern[index] = true;
+                update(&mut energy, index, 1.0);
+                ones += 1;
+            }
+        }
+
+        // Relax it by moving the tightest cluster into the largest void until stable, giving up
+        // after one move per pixel in case it oscillates
+        for _ in 0..N * N {
+            let cluster = find(&energy, &pattern, true, true);
+            pattern[cluster] = false;
+            update(&mut energy, cluster, -1.0);
+
+            let void = find(&energy, &pattern, false, false);
+            pattern[void] = true;
+            update(&mut energy, void, 1.0);
+
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut ranks = vec![0u32; N * N];
+
+        // Rank the initial pattern by removing the tightest clusters
+        let mut removing = pattern.clone();
+        let mut removing_energy = energy.clone();
+        for rank in (0..ones).rev() {
+            let cluster = find(&removing_energy, &removing, true, true);
+            removing[cluster] = false;
+            update(&mut removing_energy, cluster, -1.0);
+            ranks[cluster] = rank as u32;
+        }
+
+        // Then rank the rest by filling the largest voids
+        for rank in ones..N * N {
+            let void = find(&energy, &pattern, false, false);
+            pattern[void] = true;
+            update(&mut energy, void, 1.0);
+            ranks[void] = rank as u32;
+        }
+
+        ranks
+    })
+}
+
+/// Dither an image, reducing it to a bit depth or a palette while keeping the look of the
+/// original tones. Alpha is passed through.
+///
+/// Panics if a bit depth is outside of 1-8 or the palette is empty.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `method` - The dithering algorithm.
+/// * `target` - The bit depth or palette to reduce to.
+/// * `serpentine` - If true, error diffusion alternates direction every row, which avoids
+///   the diagonal artifacts of always scanning left to right. Ignored by ordered methods.
+pub fn dither(
+    img: DynamicImage,
+    method: DitherMethod,
+    target: &DitherTarget,
+    serpentine: bool,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let quantizer = Quantizer::new(target);
+
+    match method.kernel() {
+        Some((kernel, divisor)) => error_diffusion(img, &quantizer, kernel, divisor, serpentine),
+        None => {
+            let palette = match target {
+                DitherTarget::Palette(palette) => Some(palette),
+                _ => None,
+            };
+            ordered(img, &quantizer, quantizer.spread(palette), method)
+        }
+    }
+}
+
+fn error_diffusion(
+    img: RgbaImage,
+    quantizer: &Quantizer,
+    kernel: &[(i32, i32, f32)],
+    divisor: f32,
+    serpentine: bool,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let (w, h) = (width as i32, height as i32);
+
+    let mut values: Vec<[f32; 3]> = img
+        .pixels()
+        .map(|pixel| [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32])
+        .collect();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    // Each pixel depends on the ones before it, so this pass is sequential
+    for y in 0..h {
+        let reverse = serpentine && y % 2 == 1;
+
+        for i in 0..w {
+            let x = if reverse { w - 1 - i } else { i };
+            let index = (y * w + x) as usize;
+
+            let color = values[index];
+            let quantized = quantizer.quantize(color);
+
+            let error = [0, 1, 2].map(|c| color[c] - quantized[c] as f32);
+
+            for (dx, dy, weight) in kernel {
+                let (nx, ny) = (if reverse { x - dx } else { x + dx }, y + dy);
+                if nx < 0 || nx >= w || ny >= h {
+                    continue;
+                }
+
+                let neighbour = &mut values[(ny * w + nx) as usize];
+                for c in 0..3 {
+                    neighbour[c] += error[c] * weight / divisor;
+                }
+            }
+
+            let alpha = img.get_pixel(x as u32, y as u32)[3];
+            output.put_pixel(
+                x as u32,
+                y as u32,
+                Rgba([quantized[0], quantized[1], quantized[2], alpha]),
+            );
+        }
+    }
+
+    output
+}
+
+fn ordered(
+    img: RgbaImage,
+    quantizer: &Quantizer,
+    spread: [f32; 3],
+    method: DitherMethod,
+) -> RgbaImage {
+    let bayer;
+    let (matrix, size): (&[u32], usize) = match method {
+        DitherMethod::Bayer2 | DitherMethod::Bayer4 | DitherMethod::Bayer8 => {
+            let size = match method {
+                DitherMethod::Bayer2 => 2,
+                DitherMethod::Bayer4 => 4,
+                _ => 8,
+            };
+            bayer = bayer_matrix(size);
+            (&bayer, size)
+        }
+        _ => (blue_noise(), BLUE_NOISE_SIZE),
+    };
+
+    let count = (size * size) as f32;
+
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let rank = matrix[y as usize % size * size + x as usize % size];
+        let threshold = (rank as f32 + 0.5) / count - 0.5;
+
+        let color = [0, 1, 2].map(|c| in_pixel[c] as f32 + threshold * spread[c]);
+        let quantized = quantizer.quantize(color);
+
+        *pixel = Rgba([quantized[0], quantized[1], quantized[2], in_pixel[3]]);
+    });
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_images::solid;
+
+    const ALL: [DitherMethod; 10] = [
+        DitherMethod::FloydSteinberg,
+        DitherMethod::Atkinson,
+        DitherMethod::JarvisJudiceNinke,
+        DitherMethod::Sierra,
+        DitherMethod::SierraTwoRow,
+        DitherMethod::SierraLite,
+        DitherMethod::Bayer2,
+        DitherMethod::Bayer4,
+        DitherMethod::Bayer8,
+        DitherMethod::BlueNoise,
+    ];
+
+    fn mean_red(img: &RgbaImage) -> f64 {
+        img.pixels().map(|pixel| pixel[0] as f64).sum::<f64>() / img.pixels().len() as f64
+    }
+
+    #[test]
+    fn test_bayer_matrix() {
+        assert_eq!(bayer_matrix(2), vec![0, 2, 3, 1]);
+
+        let mut matrix = bayer_matrix(8);
+        matrix.sort();
+        assert_eq!(matrix, (0..64).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_blue_noise_is_a_permutation() {
+        let mut ranks = blue_noise().to_vec();
+        ranks.sort();
+
+        assert_eq!(
+            ranks,
+            (0..(BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as u32).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_bayer2_checkerboard() {
+        let out = dither(
+            solid(4, 4, Rgba([128, 128, 128, 200])),
+            DitherMethod::Bayer2,
+            &DitherTarget::Depth([1; 3]),
+            false,
+        );
+
+        assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 0, 200]));
+        assert_eq!(*out.get_pixel(1, 0), Rgba([255, 255, 255, 200]));
+        assert_eq!(*out.get_pixel(0, 1), Rgba([255, 255, 255, 200]));
+        assert_eq!(*out.get_pixel(1, 1), Rgba([0, 0, 0, 200]));
+    }
+
+    #[test]
+    fn test_one_bit_keeps_tone() {
+        let img = solid(32, 32, Rgba([64, 64, 64, 255]));
+
+        for method in ALL {
+            for serpentine in [false, true] {
+                let out = dither(
+                    img.clone(),
+                    method,
+                    &DitherTarget::Depth([1; 3]),
+                    serpentine,
+                );
+
+                assert!(out.pixels().all(|pixel| pixel[0] == 0 || pixel[0] == 255));
+
+                // Atkinson drops a quarter of the error by design, darkening shadows
+                if method == DitherMethod::Atkinson {
+                    continue;
+                }
+
+                assert!(
+                    (mean_red(&out) - 64.0).abs() < 8.0,
+                    "{:?} mean {}",
+                    method,
+                    mean_red(&out)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(16, 16, |x, y| {
+            Rgba([(x * 16) as u8, (y * 16) as u8, 100, 255])
+        }));
+
+        for method in ALL {
+            let a = dither(img.clone(), method, &DitherTarget::Depth([2, 3, 1]), true);
+            let b = dither(img.clone(), method, &DitherTarget::Depth([2, 3, 1]), true);
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_full_depth_is_identity() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(8, 8, |x, y| {
+            Rgba([(x * 30) as u8, (y * 30) as u8, (x * y) as u8, 255])
+        }));
+
+        let out = dither(
+            img.clone(),
+            DitherMethod::FloydSteinberg,
+            &DitherTarget::Depth([8; 3]),
+            false,
+        );
+
+        assert_eq!(out, img.to_rgba8());
+    }
+
+    #[test]
+    fn test_palette_target() {
+        let palette = Palette::new(vec![Rgb([0, 0, 0]), Rgb([255, 0, 0]), Rgb([255, 255, 255])]);
+        let img = solid(16, 16, Rgba([200, 60, 60, 255]));
+
+        for method in ALL {
+            let out = dither(
+                img.clone(),
+                method,
+                &DitherTarget::Palette(palette.clone()),
+                true,
+            );
+
+            assert!(out.pixels().all(|pixel| palette
+                .colors
+                .contains(&Rgb([pixel[0], pixel[1], pixel[2]]))));
+        }
+    }
+}
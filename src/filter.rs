@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use crate::{calc_luminance, rgb_to_hsv, utils::get_channel_by_name_rgba_u8};
-use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
+use image::{DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma, Rgba, RgbaImage};
 use rayon::prelude::*;
 
 /// Specify whether the filter should replace colors that are INCLUDED in the range or EXCLUDED
@@ -65,6 +65,21 @@ impl FromStr for FilterParam {
     }
 }
 
+impl FilterParam {
+    /// The value of the property for a pixel, normalised to 0.0-1.0.
+    pub fn normalised(&self, pixel: &Rgba<u8>) -> f64 {
+        match self {
+            FilterParam::Luminance => calc_luminance(*pixel) / 255.0,
+            FilterParam::Red => pixel[0] as f64 / 255.0,
+            FilterParam::Green => pixel[1] as f64 / 255.0,
+            FilterParam::Blue => pixel[2] as f64 / 255.0,
+            FilterParam::Hue => rgb_to_hsv(*pixel).0 / 360.0,
+            FilterParam::Saturation => rgb_to_hsv(*pixel).1,
+            FilterParam::Value => rgb_to_hsv(*pixel).2,
+        }
+    }
+}
+
 /// A threshold range that the filter will check in between. This is a dedicated struct because
 /// for a CLI frontend, I want to minimize String usage after the initial arg parsing.
 #[derive(Clone, Copy, Debug)]
@@ -207,6 +222,41 @@ pub fn filter(
     output
 }
 
+/// Perform the filter as a mask instead of replacing colors. Pixels the filter would replace
+/// are 255 and the rest are 0. lhs will remap the colors before filtering.
+pub fn filter_mask(img: DynamicImage, lhs: Option<Vec<String>>, filter: Filter) -> GrayImage {
+    let (width, height) = img.dimensions();
+
+    let mut output: GrayImage = ImageBuffer::new(width, height);
+
+    let filter_type = filter.filter_type;
+    let filter_sorter = generate_filter(filter);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let lhs = match lhs {
+            Some(ref lhs) => (
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+            ),
+            None => (in_pixel[0], in_pixel[1], in_pixel[2]),
+        };
+
+        let passes = filter_sorter(&Rgba([lhs.0, lhs.1, lhs.2, 255u8]));
+
+        let replaced = match filter_type {
+            FilterType::Include => !passes,
+            FilterType::Exclude => passes,
+        };
+
+        *pixel = Luma([if replaced { 255 } else { 0 }]);
+    });
+
+    output
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -232,4 +282,53 @@ mod tests {
         let pixel = img.get_pixel(0, 0);
         return pixel.to_rgb();
     }
+
+    #[test]
+    fn test_filter() {
+        let red = load_image("ff0000.png".to_string());
+        let control_color = get_color_from_control(red.clone());
+
+        let out = filter(
+            red.clone(),
+            None,
+            Filter {
+                filter_type: FilterType::Exclude,
+                filter_param: FilterParam::Red,
+                threshold_ranges: parse_filter_vec(vec!["200".to_string(), "256".to_string()]),
+            },
+            Rgba([0, 0, 255, 255]),
+        );
+
+        const EXPECTED: Rgb<u8> = Rgb([0, 0, 255]);
+
+        assert_ne!(control_color, EXPECTED);
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
+    }
+
+    #[test]
+    fn test_filter_mask() {
+        let red = load_image("ff0000.png".to_string());
+
+        let mask = filter_mask(
+            red.clone(),
+            None,
+            Filter {
+                filter_type: FilterType::Exclude,
+                filter_param: FilterParam::Red,
+                threshold_ranges: parse_filter_vec(vec!["200".to_string(), "256".to_string()]),
+            },
+        );
+        assert_eq!(mask.get_pixel(0, 0), &Luma([255]));
+
+        let mask = filter_mask(
+            red,
+            None,
+            Filter {
+                filter_type: FilterType::Exclude,
+                filter_param: FilterParam::Green,
+                threshold_ranges: parse_filter_vec(vec!["200".to_string(), "256".to_string()]),
+            },
+        );
+        assert_eq!(mask.get_pixel(0, 0), &Luma([0]));
+    }
 }
@@ -1,18 +1,143 @@
-use crate::utils::{get_channel_by_name_rgb_color, get_channel_by_name_rgba_u8};
+use crate::utils::{
+    apply_luts, calc_luminance, get_channel_by_name_rgb_color, get_channel_by_name_rgba_u8,
+    get_channel_index_by_name, mix_pixels, rgb_to_lab, Mix,
+};
 use image::{
     imageops::fast_blur, DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba, RgbaImage,
 };
 use rayon::prelude::*;
+use std::str::FromStr;
+
+/// How greyscale turns a color into a single grey level.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GreyscaleMethod {
+    /// Rec. 601 luma, 0.299 R + 0.587 G + 0.114 B, as used by SD video and JPEG.
+    Rec601,
+    /// Rec. 709 luma, 0.2126 R + 0.7152 G + 0.0722 B, the same weights as `calc_luminance`.
+    Rec709,
+    /// Mean of R, G and B.
+    Average,
+    /// Midpoint of the largest and smallest channel, the L of HSL.
+    Lightness,
+    /// A single channel, 0 for red, 1 for green and 2 for blue.
+    Channel(usize),
+    /// Custom R, G and B weights, normalised to sum to 1.
+    Weights([f64; 3]),
+    /// Perceptual lightness, L* of CIE Lab scaled to 0-255.
+    LabLightness,
+}
+
+/// Clap FromStr. A method name, a channel ("r", "g" or "b") or three comma separated weights.
+impl FromStr for GreyscaleMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['.', '-', '_', ' '], "").as_str() {
+            "rec601" | "bt601" => Ok(GreyscaleMethod::Rec601),
+            "rec709" | "bt709" => Ok(GreyscaleMethod::Rec709),
+            "average" => Ok(GreyscaleMethod::Average),
+            "lightness" => Ok(GreyscaleMethod::Lightness),
+            "lab" | "l*" => Ok(GreyscaleMethod::LabLightness),
+            channel @ ("r" | "g" | "b") => Ok(GreyscaleMethod::Channel(
+                get_channel_index_by_name(channel).unwrap(),
+            )),
+            // Weights are parsed from the original text, the decimal points and signs matter
+            _ => match s
+                .trim()
+                .split(',')
+                .map(|weight| weight.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .as_deref()
+            {
+                Ok([r, g, b]) => Ok(GreyscaleMethod::Weights([*r, *g, *b])),
+
+                _ => Err(format!("Invalid GreyscaleMethod: {}", s)),
+            },
+        }
+    }
+}
+
+impl GreyscaleMethod {
+    /// The grey level of a color.
+    pub fn grey(&self, color: [u8; 3]) -> u8 {
+        let [r, g, b] = color.map(|channel| channel as f64);
+
+        let weighted = |w: [f64; 3]| {
+            let sum = w[0] + w[1] + w[2];
+            if sum == 0.0 {
+                0.0
+            } else {
+                (w[0] * r + w[1] * g + w[2] * b) / sum
+            }
+        };
+
+        let grey = match self {
+            GreyscaleMethod::Rec601 => weighted([0.299, 0.587, 0.114]),
+            GreyscaleMethod::Rec709 => weighted([0.2126, 0.7152, 0.0722]),
+            GreyscaleMethod::Average => (r + g + b) / 3.0,
+            GreyscaleMethod::Lightness => (r.max(g).max(b) + r.min(g).min(b)) / 2.0,
+            GreyscaleMethod::Channel(channel) => color[(*channel).min(2)] as f64,
+            GreyscaleMethod::Weights(weights) => weighted(*weights),
+            GreyscaleMethod::LabLightness => rgb_to_lab(Rgb(color)).0 * 2.55,
+        };
 
-pub fn greyscale(img: DynamicImage) -> RgbaImage {
-    return Into::into(img.grayscale());
+        grey.round().clamp(0.0, 255.0) as u8
+    }
 }
 
+/// Greyscale. Alpha is passed through.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings remapping the channels before the grey level is computed.
+/// * `method` - How colors are turned into grey, see GreyscaleMethod. Rec709 matches
+///   image::DynamicImage::grayscale.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn greyscale(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    method: GreyscaleMethod,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let lhs = match lhs {
+            Some(ref lhs) => [
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+            ],
+            None => [in_pixel[0], in_pixel[1], in_pixel[2]],
+        };
+
+        let grey = method.grey(lhs);
+
+        *pixel = mix_pixels(
+            Rgba([lhs[0], lhs[1], lhs[2], in_pixel[3]]),
+            Rgba([grey, grey, grey, in_pixel[3]]),
+            mix,
+        );
+    });
+
+    output
+}
+
+/// Average of each pixel and a color.
+/// RGB channels are remappable before operation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings to remap the order of the channels of the left-hand side.
+/// * `rhs` - Optional vector of Strings to remap the order of the channels of the right-hand side.
+/// * `color` - The right-hand side of the operation.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
 pub fn average(
     img: DynamicImage,
     lhs: Option<Vec<String>>,
     rhs: Option<Vec<String>>,
     color: Rgb<u8>,
+    mix: Option<Mix>,
 ) -> RgbaImage {
     let r = color.0[0];
     let g = color.0[1];
@@ -51,69 +176,502 @@ pub fn average(
 
         let a = in_pixel[3];
 
-        *pixel = Rgba([r, g, b, a]);
+        *pixel = mix_pixels(Rgba([lhs.0, lhs.1, lhs.2, a]), Rgba([r, g, b, a]), mix);
     });
 
     output
 }
 
+/// Posterize, reducing each channel to evenly spaced levels.
+///
+/// Panics if a channel has fewer than 2 levels.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings choosing the source channel of each output channel.
+/// * `levels` - Number of levels for the R, G and B channels, 2-256.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn posterize(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    levels: [u16; 3],
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let luts = levels.map(|levels| {
+        assert!(
+            (2..=256).contains(&levels),
+            "Invalid number of levels: {}",
+            levels
+        );
+        let steps = (levels - 1) as f64;
+
+        std::array::from_fn(|v| {
+            let level = (v as f64 / 255.0 * steps).round();
+            (level * 255.0 / steps).round() as u8
+        })
+    });
+
+    apply_luts(img, lhs, &luts, mix)
+}
+
+/// Where threshold splits a channel into black and white.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ThresholdLevel {
+    /// Values at or above the level become 255.
+    Global(u8),
+    /// The level is picked per channel with Otsu's method.
+    Otsu,
+}
+
+/// Clap FromStr, a level 0-255 or "otsu".
+impl FromStr for ThresholdLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "otsu" => Ok(ThresholdLevel::Otsu),
+            level => level
+                .parse::<u8>()
+                .map(ThresholdLevel::Global)
+                .map_err(|_| format!("Invalid ThresholdLevel: {}", s)),
+        }
+    }
+}
+
+/// Otsu's method. Finds the level that best separates a histogram into two classes, by
+/// maximising the variance between them. Values at or above the level belong to the upper class.
+pub fn otsu_threshold(histogram: &[u64; 256]) -> u8 {
+    let total: u64 = histogram.iter().sum();
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(v, count)| v as f64 * *count as f64)
+        .sum();
+
+    let mut best = (0, -1.0);
+    let mut weight_lower = 0u64;
+    let mut sum_lower = 0.0;
+
+    for level in 1..256 {
+        weight_lower += histogram[level - 1];
+        sum_lower += (level - 1) as f64 * histogram[level - 1] as f64;
+
+        let weight_upper = total - weight_lower;
+        if weight_lower == 0 || weight_upper == 0 {
+            continue;
+        }
+
+        let mean_lower = sum_lower / weight_lower as f64;
+        let mean_upper = (sum - sum_lower) / weight_upper as f64;
+
+        let variance =
+            weight_lower as f64 * weight_upper as f64 * (mean_lower - mean_upper).powi(2);
+
+        if variance > best.1 {
+            best = (level, variance);
+        }
+    }
+
+    best.0 as u8
+}
+
+/// Binary threshold. Each channel becomes 0 below the level and 255 at or above it.
+/// Run greyscale first for a black and white result.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings choosing the source channel of each output channel.
+/// * `level` - A fixed level, or Otsu to pick one per channel from the image.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn threshold(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    level: ThresholdLevel,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let levels = match level {
+        ThresholdLevel::Global(level) => [level; 3],
+        ThresholdLevel::Otsu => {
+            let mut histograms = [[0u64; 256]; 3];
+
+            for (_, _, pixel) in img.pixels() {
+                for (i, histogram) in histograms.iter_mut().enumerate() {
+                    let value = match lhs {
+                        Some(ref lhs) => get_channel_by_name_rgba_u8(&lhs[i], &pixel),
+                        None => pixel[i],
+                    };
+                    histogram[value as usize] += 1;
+                }
+            }
+
+            histograms.map(|histogram| otsu_threshold(&histogram))
+        }
+    };
+
+    let luts =
+        levels.map(|level| std::array::from_fn(|v| if v >= level as usize { 255 } else { 0 }));
+
+    apply_luts(img, lhs, &luts, mix)
+}
+
+/// Input and output ranges of a levels adjustment.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Levels {
+    /// Input value mapped to the output black.
+    pub in_black: u8,
+    /// Input value mapped to the output white.
+    pub in_white: u8,
+    /// Midtone gamma. Above 1.0 brightens, below darkens.
+    pub gamma: f64,
+    pub out_black: u8,
+    pub out_white: u8,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Levels {
+            in_black: 0,
+            in_white: 255,
+            gamma: 1.0,
+            out_black: 0,
+            out_white: 255,
+        }
+    }
+}
+
+impl Levels {
+    pub fn new(in_black: u8, in_white: u8, gamma: f64, out_black: u8, out_white: u8) -> Self {
+        Levels {
+            in_black,
+            in_white,
+            gamma,
+            out_black,
+            out_white,
+        }
+    }
+
+    /// Bake the adjustment into a lookup table.
+    pub fn lut(&self) -> [u8; 256] {
+        let range = (self.in_white as f64 - self.in_black as f64).max(1.0);
+        let gamma = if self.gamma > 0.0 { self.gamma } else { 1.0 };
+
+        std::array::from_fn(|v| {
+            let t = ((v as f64 - self.in_black as f64) / range).clamp(0.0, 1.0);
+            let t = t.powf(1.0 / gamma);
+
+            let out = self.out_black as f64 + t * (self.out_white as f64 - self.out_black as f64);
+            out.round().clamp(0.0, 255.0) as u8
+        })
+    }
+}
+
+/// Clap FromStr, "in_black,in_white,gamma,out_black,out_white" or just
+/// "in_black,in_white,gamma".
+impl FromStr for Levels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values: Vec<&str> = s.split(',').map(|value| value.trim()).collect();
+
+        let byte = |value: &str| {
+            value
+                .parse::<u8>()
+                .map_err(|_| format!("Invalid Levels: {}", s))
+        };
+        let gamma = |value: &str| {
+            value
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid Levels: {}", s))
+        };
+
+        match values.as_slice() {
+            [ib, iw, g] => Ok(Levels::new(byte(ib)?, byte(iw)?, gamma(g)?, 0, 255)),
+            [ib, iw, g, ob, ow] => Ok(Levels::new(
+                byte(ib)?,
+                byte(iw)?,
+                gamma(g)?,
+                byte(ob)?,
+                byte(ow)?,
+            )),
+
+            _ => Err(format!("Invalid Levels: {}", s)),
+        }
+    }
+}
+
+/// Levels adjustment. Stretches the input range of each channel to the output range, with a
+/// gamma curve for the midtones.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings choosing the source channel of each output channel.
+/// * `levels` - The adjustment of the R, G and B channels.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn levels(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    levels: [Levels; 3],
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let luts = levels.map(|levels| levels.lut());
+
+    apply_luts(img, lhs, &luts, mix)
+}
+
+/// Shape of the glow produced by bloom.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BloomStyle {
+    /// Round glow, a gaussian blur of the light mask.
+    Gaussian,
+    /// Horizontal lens streaks. The light mask is only blurred horizontally, with the blur radius
+    /// multiplied by `stretch`.
+    Anamorphic { stretch: f64 },
+    /// Star/cross streaks. The light mask is smeared along each angle (in degrees, 0 is
+    /// horizontal) with an exponential falloff, reaching `length` pixels in both directions.
+    /// The blur radius is not used, `length` alone sets the size of the streaks.
+    Streak { angles: Vec<f64>, length: f64 },
+}
+
+/// Clap FromStr
+impl FromStr for BloomStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gaussian" => Ok(BloomStyle::Gaussian),
+            "anamorphic" => Ok(BloomStyle::Anamorphic { stretch: 8.0 }),
+            "cross" => Ok(BloomStyle::Streak {
+                angles: vec![0.0, 90.0],
+                length: 64.0,
+            }),
+            "star" => Ok(BloomStyle::Streak {
+                angles: vec![0.0, 45.0, 90.0, 135.0],
+                length: 64.0,
+            }),
+
+            _ => Err(format!("Invalid BloomStyle name: {}", s)),
+        }
+    }
+}
+
+/// How the bloom glow looks, on top of its brightness and size.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BloomOptions {
+    /// Shape of the glow, see BloomStyle.
+    pub style: BloomStyle,
+    /// Optional color the glow is multiplied by.
+    pub tint: Option<Rgb<u8>>,
+}
+
+impl Default for BloomOptions {
+    /// Untinted gaussian glow.
+    fn default() -> Self {
+        BloomOptions {
+            style: BloomStyle::Gaussian,
+            tint: None,
+        }
+    }
+}
+
+/// Bloom effect.
+/// Extracts the pixels whose luminance falls within the threshold range, blurs them and adds
+/// the blurred light back onto the image.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `intensity` - Multiplier for the blurred light before it is added to the image.
+/// * `blur_radius` - Sigma of the blur applied to the light mask.
+/// * `min_threshold` - Pixels must be brighter than this luminance to glow.
+/// * `max_threshold` - Optional upper luminance bound for pixels that glow.
+/// * `options` - Style and tint of the glow, see BloomOptions.
 pub fn bloom(
     img: DynamicImage,
     intensity: f64,
     blur_radius: f64,
     min_threshold: u8,
     max_threshold: Option<u8>,
+    options: &BloomOptions,
 ) -> RgbaImage {
-    let (width, height) = img.dimensions();
+    let rgba_img = img.into_rgba8();
 
-    let rgba_img = img.to_rgba8();
+    let (width, height) = rgba_img.dimensions();
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
 
-    let mut light_mask: RgbaImage = ImageBuffer::new(width, height);
+    bloom_into(
+        &rgba_img,
+        &mut output,
+        intensity,
+        blur_radius,
+        min_threshold,
+        max_threshold,
+        options,
+    );
 
-    for (x, y, pixel) in rgba_img.enumerate_pixels() {
-        let r = pixel[0];
-        let g = pixel[1];
-        let b = pixel[2];
+    output
+}
 
-        //  = 0.2126 R + 0.7152 G + 0.0722 B
-        let luminance = 0.2126 * (r as f64) + 0.7152 * (g as f64) + 0.0722 * (b as f64);
+/// Bloom effect writing into a preallocated output buffer.
+///
+/// Meant for processing many frames of the same size, e.g. video, where the output buffer can be
+/// reused between calls. The output buffer doubles as the light mask, so only the blurred light
+/// is allocated per call.
+///
+/// Panics if `output` does not have the same dimensions as `img`.
+/// * `img` - The image::RgbaImage input to perform the operation on.
+/// * `output` - The buffer the result is written into.
+/// * `intensity` - Multiplier for the blurred light before it is added to the image.
+/// * `blur_radius` - Sigma of the blur applied to the light mask.
+/// * `min_threshold` - Pixels must be brighter than this luminance to glow.
+/// * `max_threshold` - Optional upper luminance bound for pixels that glow.
+/// * `options` - Style and tint of the glow, see BloomOptions.
+pub fn bloom_into(
+    img: &RgbaImage,
+    output: &mut RgbaImage,
+    intensity: f64,
+    blur_radius: f64,
+    min_threshold: u8,
+    max_threshold: Option<u8>,
+    options: &BloomOptions,
+) {
+    assert_eq!(
+        img.dimensions(),
+        output.dimensions(),
+        "bloom output buffer must match the input dimensions"
+    );
 
-        match max_threshold {
-            Some(threshold) => {
-                if luminance > min_threshold as f64 && luminance < threshold as f64 {
-                    light_mask.put_pixel(x, y, Rgba([r, g, b, pixel[3]]));
-                } else {
-                    light_mask.put_pixel(x, y, Rgba([0, 0, 0, 0]));
-                }
-            }
-            None => {
-                if luminance > min_threshold as f64 {
-                    light_mask.put_pixel(x, y, Rgba([r, g, b, pixel[3]]));
-                } else {
-                    light_mask.put_pixel(x, y, Rgba([0, 0, 0, 0]));
-                }
-            }
+    let max_threshold = max_threshold.map_or(f64::INFINITY, |threshold| threshold as f64);
+
+    // Threshold pass, using the output buffer as the light mask
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let luminance = calc_luminance(*in_pixel);
+
+        if luminance > min_threshold as f64 && luminance < max_threshold {
+            *pixel = *in_pixel;
+        } else {
+            *pixel = Rgba([0, 0, 0, 0]);
         }
-    }
+    });
 
-    let blurred_light = fast_blur(&light_mask, blur_radius as f32);
+    let blurred_light = match &options.style {
+        BloomStyle::Gaussian => fast_blur(output, blur_radius as f32),
+        BloomStyle::Anamorphic { stretch } => horizontal_blur(output, blur_radius * stretch),
+        BloomStyle::Streak { angles, length } => streak_blur(output, angles, *length),
+    };
 
-    let mut output: RgbaImage = ImageBuffer::new(width, height);
+    let tint = match options.tint {
+        Some(tint) => (
+            tint.0[0] as f64 / 255.0,
+            tint.0[1] as f64 / 255.0,
+            tint.0[2] as f64 / 255.0,
+        ),
+        None => (1.0, 1.0, 1.0),
+    };
 
-    for (x, y, pixel) in rgba_img.enumerate_pixels() {
+    // Composite pass, blend the blurred light with the original image
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
         let blurred_pixel = blurred_light.get_pixel(x, y);
 
-        // Blend the blurred light with the original image
         let (r, g, b) = (
-            ((pixel[0] as f64) + (blurred_pixel[0] as f64 * intensity)).min(255.0) as u8,
-            ((pixel[1] as f64) + (blurred_pixel[1] as f64 * intensity)).min(255.0) as u8,
-            ((pixel[2] as f64) + (blurred_pixel[2] as f64 * intensity)).min(255.0) as u8,
+            ((in_pixel[0] as f64) + (blurred_pixel[0] as f64 * tint.0 * intensity)).min(255.0)
+                as u8,
+            ((in_pixel[1] as f64) + (blurred_pixel[1] as f64 * tint.1 * intensity)).min(255.0)
+                as u8,
+            ((in_pixel[2] as f64) + (blurred_pixel[2] as f64 * tint.2 * intensity)).min(255.0)
+                as u8,
         );
 
-        let a = pixel[3];
+        let a = in_pixel[3];
+
+        *pixel = Rgba([r, g, b, a]);
+    });
+}
+
+/// Approximate a horizontal gaussian blur with three box blur passes over each row.
+fn horizontal_blur(img: &RgbaImage, sigma: f64) -> RgbaImage {
+    let width = img.width();
+
+    // Box radius whose three passes have the same variance as the gaussian
+    let radius = (((12.0 * sigma * sigma / 3.0 + 1.0).sqrt() - 1.0) / 2.0).round() as i64;
+
+    let mut output = img.clone();
+
+    if radius < 1 || width == 0 {
+        return output;
+    }
+
+    let row_len = width as usize * 4;
+
+    output.par_chunks_mut(row_len).for_each(|row| {
+        let mut scratch = vec![0u8; row_len];
+
+        for _ in 0..3 {
+            box_blur_row(row, &mut scratch, radius);
+            row.copy_from_slice(&scratch);
+        }
+    });
+
+    output
+}
+
+/// Running sum box blur of one row of RGBA pixels, clamping at the edges.
+fn box_blur_row(row: &[u8], out: &mut [u8], radius: i64) {
+    let width = (row.len() / 4) as i64;
+    let window = (2 * radius + 1) as f64;
+
+    for channel in 0..4 {
+        let at = |x: i64| row[x.clamp(0, width - 1) as usize * 4 + channel] as f64;
+
+        let mut sum: f64 = (-radius..=radius).map(at).sum();
 
-        output.put_pixel(x, y, Rgba([r, g, b, a]));
+        for x in 0..width {
+            out[x as usize * 4 + channel] = (sum / window).round() as u8;
+            sum += at(x + radius + 1) - at(x - radius);
+        }
     }
+}
+
+/// Smear the light mask along each angle, averaging the streaks of all angles.
+fn streak_blur(img: &RgbaImage, angles: &[f64], length: f64) -> RgbaImage {
+    let (width, height) = img.dimensions();
+
+    if angles.is_empty() || length < 1.0 {
+        return img.clone();
+    }
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    let steps = length.ceil() as i64;
+    let falloff = length / 3.0;
+
+    // Step vectors and weights shared by every pixel
+    let directions: Vec<(f64, f64)> = angles
+        .iter()
+        .map(|angle| {
+            let radians = angle.to_radians();
+            (radians.cos(), radians.sin())
+        })
+        .collect();
+    let weights: Vec<f64> = (-steps..=steps)
+        .map(|t| (-(t.abs() as f64) / falloff).exp())
+        .collect();
+    let weight_sum: f64 = weights.iter().sum::<f64>() * directions.len() as f64;
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let mut acc = [0.0f64; 4];
+
+        for (dx, dy) in directions.iter() {
+            for (t, weight) in (-steps..=steps).zip(weights.iter()) {
+                let sx = (x as f64 + dx * t as f64).round();
+                let sy = (y as f64 + dy * t as f64).round();
+
+                if sx < 0.0 || sy < 0.0 || sx >= width as f64 || sy >= height as f64 {
+                    continue;
+                }
+
+                let sample = img.get_pixel(sx as u32, sy as u32);
+                for (channel, value) in acc.iter_mut().enumerate() {
+                    *value += sample[channel] as f64 * weight;
+                }
+            }
+        }
+
+        *pixel = Rgba(acc.map(|value| (value / weight_sum).round().min(255.0) as u8));
+    });
 
     output
 }
@@ -149,7 +707,7 @@ mod tests {
         let red = load_image("ff0000.png".to_string());
         let control_color = get_color_from_control(red.clone());
 
-        let out = average(red.clone(), None, None, Rgb([0, 0, 255]));
+        let out = average(red.clone(), None, None, Rgb([0, 0, 255]), None);
 
         println!(
             "{:?} == {:?}",
@@ -159,7 +717,10 @@ mod tests {
 
         const EXPECTED: Rgb<u8> = Rgb([127, 0, 127]);
 
-        assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED);
+
+        let out = average(red, None, None, Rgb([0, 0, 255]), Some(Mix::uniform(0.5)));
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([191, 0, 64]))
     }
 
     #[test]
@@ -167,7 +728,14 @@ mod tests {
         let red = load_image("ff0000.png".to_string());
         let control_color = get_color_from_control(red.clone());
 
-        let out = bloom(red.clone(), 1.0, 1.0, 0, Some(255));
+        let out = bloom(
+            red.clone(),
+            1.0,
+            1.0,
+            0,
+            Some(255),
+            &BloomOptions::default(),
+        );
 
         println!(
             "{:?} == {:?}",
@@ -179,4 +747,218 @@ mod tests {
 
         assert_eq!(out.get_pixel(0, 0).to_rgb(), EXPECTED)
     }
+
+    #[test]
+    fn test_bloom_into() {
+        let red = load_image("ff0000.png".to_string());
+        let (width, height) = red.dimensions();
+
+        let expected = bloom(
+            red.clone(),
+            1.0,
+            1.0,
+            0,
+            Some(255),
+            &BloomOptions::default(),
+        );
+
+        let mut out: RgbaImage = ImageBuffer::new(width, height);
+        bloom_into(
+            &red.to_rgba8(),
+            &mut out,
+            1.0,
+            1.0,
+            0,
+            Some(255),
+            &BloomOptions::default(),
+        );
+
+        assert_eq!(out, expected)
+    }
+
+    fn light_point() -> DynamicImage {
+        let mut img: RgbaImage = ImageBuffer::from_pixel(33, 33, Rgba([0, 0, 0, 255]));
+        img.put_pixel(16, 16, Rgba([255, 255, 255, 255]));
+        DynamicImage::ImageRgba8(img)
+    }
+
+    #[test]
+    fn test_bloom_tint() {
+        let out = bloom(
+            light_point(),
+            1.0,
+            2.0,
+            128,
+            None,
+            &BloomOptions {
+                style: BloomStyle::Gaussian,
+                tint: Some(Rgb([255, 0, 0])),
+            },
+        );
+
+        let glow = out.get_pixel(17, 16).to_rgb();
+
+        assert!(glow[0] > 0);
+        assert_eq!((glow[1], glow[2]), (0, 0))
+    }
+
+    #[test]
+    fn test_bloom_anamorphic() {
+        let out = bloom(
+            light_point(),
+            1.0,
+            1.0,
+            128,
+            None,
+            &BloomOptions {
+                style: BloomStyle::Anamorphic { stretch: 4.0 },
+                tint: None,
+            },
+        );
+
+        assert!(out.get_pixel(20, 16)[0] > 0);
+        assert_eq!(out.get_pixel(16, 18).to_rgb(), Rgb([0, 0, 0]))
+    }
+
+    #[test]
+    fn test_bloom_streak() {
+        let out = bloom(
+            light_point(),
+            4.0,
+            1.0,
+            128,
+            None,
+            &BloomOptions {
+                style: BloomStyle::Streak {
+                    angles: vec![0.0, 90.0],
+                    length: 8.0,
+                },
+                tint: None,
+            },
+        );
+
+        assert!(out.get_pixel(20, 16)[0] > 0);
+        assert!(out.get_pixel(16, 20)[0] > 0);
+        assert_eq!(out.get_pixel(20, 20).to_rgb(), Rgb([0, 0, 0]))
+    }
+
+    fn ramp() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(256, 1, |x, _| {
+            Rgba([x as u8, x as u8, 255 - x as u8, 128])
+        }))
+    }
+
+    #[test]
+    fn test_posterize() {
+        let out = posterize(ramp(), None, [2, 3, 256], None);
+
+        assert_eq!(*out.get_pixel(100, 0), Rgba([0, 128, 155, 128]));
+        assert_eq!(*out.get_pixel(200, 0), Rgba([255, 255, 55, 128]));
+
+        let mut reds: Vec<u8> = out.pixels().map(|pixel| pixel[0]).collect();
+        reds.dedup();
+        assert_eq!(reds, vec![0, 255]);
+    }
+
+    #[test]
+    fn test_threshold() {
+        let out = threshold(ramp(), None, ThresholdLevel::Global(100), None);
+
+        assert_eq!(*out.get_pixel(99, 0), Rgba([0, 0, 255, 128]));
+        assert_eq!(*out.get_pixel(100, 0), Rgba([255, 255, 255, 128]));
+    }
+
+    #[test]
+    fn test_otsu() {
+        // Two clusters around 40 and 200
+        let mut histogram = [0u64; 256];
+        histogram[30..=50].fill(10);
+        histogram[190..=210].fill(10);
+
+        let level = otsu_threshold(&histogram);
+        assert!(level > 50 && level <= 190, "{}", level);
+
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 1, |x, _| {
+            let v = if x < 2 { 40 } else { 200 };
+            Rgba([v, v, v, 255])
+        }));
+
+        let out = threshold(img, None, "otsu".parse().unwrap(), None);
+        assert_eq!(*out.get_pixel(1, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(2, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_levels() {
+        let stretch = Levels::new(50, 150, 1.0, 0, 255);
+        let lut = stretch.lut();
+        assert_eq!((lut[40], lut[100], lut[160]), (0, 128, 255));
+
+        let gamma = "0,255,2.0".parse::<Levels>().unwrap().lut();
+        assert_eq!(gamma[64], 128);
+
+        let out = levels(
+            ramp(),
+            Some(vec!["b".to_string(), "g".to_string(), "r".to_string()]),
+            [
+                Levels::default(),
+                Levels::new(0, 255, 1.0, 100, 200),
+                Levels::default(),
+            ],
+            None,
+        );
+        assert_eq!(*out.get_pixel(255, 0), Rgba([0, 200, 255, 128]));
+        assert_eq!(*out.get_pixel(0, 0), Rgba([255, 100, 0, 128]));
+    }
+
+    #[test]
+    fn test_greyscale_methods() {
+        let color = [200, 100, 50];
+
+        assert_eq!(GreyscaleMethod::Rec601.grey(color), 124);
+        assert_eq!(GreyscaleMethod::Rec709.grey(color), 118);
+        assert_eq!(GreyscaleMethod::Average.grey(color), 117);
+        assert_eq!(GreyscaleMethod::Lightness.grey(color), 125);
+        assert_eq!("b".parse::<GreyscaleMethod>().unwrap().grey(color), 50);
+        assert_eq!("1,1,0".parse::<GreyscaleMethod>().unwrap().grey(color), 150);
+        assert_eq!(
+            "0.5,0.25,0.25".parse::<GreyscaleMethod>().unwrap(),
+            GreyscaleMethod::Weights([0.5, 0.25, 0.25])
+        );
+        assert_eq!(
+            "0.5, 0.25, 0.25"
+                .parse::<GreyscaleMethod>()
+                .unwrap()
+                .grey(color),
+            138
+        );
+        assert_eq!(
+            "1,-0.5,0.5".parse::<GreyscaleMethod>().unwrap(),
+            GreyscaleMethod::Weights([1.0, -0.5, 0.5])
+        );
+
+        // L* of white is 100 and of black 0
+        assert_eq!(GreyscaleMethod::LabLightness.grey([255, 255, 255]), 255);
+        assert_eq!(GreyscaleMethod::LabLightness.grey([0, 0, 0]), 0);
+        assert_eq!(GreyscaleMethod::LabLightness.grey([0, 0, 255]), 82);
+
+        assert!("1,2".parse::<GreyscaleMethod>().is_err());
+    }
+
+    #[test]
+    fn test_greyscale() {
+        let red = load_image("ff0000.png".to_string());
+
+        // Rec. 709 matches the image crate
+        let out = greyscale(red.clone(), None, GreyscaleMethod::Rec709, None);
+        assert_eq!(out, Into::<RgbaImage>::into(red.grayscale()));
+
+        let out = greyscale(
+            red,
+            Some(vec!["b".to_string(), "r".to_string(), "b".to_string()]),
+            GreyscaleMethod::Rec709,
+            None,
+        );
+        assert_eq!(out.get_pixel(0, 0).to_rgb(), Rgb([182, 182, 182]));
+    }
 }
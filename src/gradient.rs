@@ -0,0 +1,364 @@
+use std::str::FromStr;
+
+use crate::filter::FilterParam;
+use crate::utils::{
+    get_channel_by_name_rgba_u8, hex_to_rgb, linear_srgb_to_oklab, linear_to_srgb, mix_pixels,
+    oklab_to_linear_srgb, srgb_to_linear, Mix,
+};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Color space the gradient is interpolated in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GradientSpace {
+    /// Plain sRGB values. Midpoints between saturated colors come out dark.
+    Srgb,
+    /// Linear light, physically correct blending but midpoints look bright.
+    Linear,
+    /// Oklab, perceptually even steps in lightness and hue.
+    Oklab,
+}
+
+/// Clap FromStr
+impl FromStr for GradientSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "srgb" => Ok(GradientSpace::Srgb),
+            "linear" => Ok(GradientSpace::Linear),
+            "oklab" => Ok(GradientSpace::Oklab),
+
+            _ => Err(format!("Invalid GradientSpace name: {}", s)),
+        }
+    }
+}
+
+impl GradientSpace {
+    fn encode(&self, color: Rgb<u8>) -> [f64; 3] {
+        let srgb = color.0.map(|channel| channel as f64 / 255.0);
+
+        match self {
+            GradientSpace::Srgb => srgb,
+            GradientSpace::Linear => srgb.map(srgb_to_linear),
+            GradientSpace::Oklab => linear_srgb_to_oklab(srgb.map(srgb_to_linear)),
+        }
+    }
+
+    fn decode(&self, color: [f64; 3]) -> Rgb<u8> {
+        let srgb = match self {
+            GradientSpace::Srgb => color,
+            GradientSpace::Linear => color.map(|c| linear_to_srgb(c.clamp(0.0, 1.0))),
+            GradientSpace::Oklab => {
+                oklab_to_linear_srgb(color).map(|c| linear_to_srgb(c.clamp(0.0, 1.0)))
+            }
+        };
+
+        Rgb(srgb.map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8))
+    }
+}
+
+/// A color gradient through stops at positions 0.0-1.0. Before the first stop and after the last
+/// one the gradient holds the stop's color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    stops: Vec<(f64, Rgb<u8>)>,
+}
+
+impl Gradient {
+    /// Build a gradient from (position, color) stops, in any order.
+    ///
+    /// Fails if there are no stops or a position is outside of 0.0-1.0.
+    pub fn new(stops: Vec<(f64, Rgb<u8>)>) -> Result<Self, String> {
+        let mut stops = stops;
+
+        if stops.is_empty() {
+            return Err("Gradient has no stops".to_string());
+        }
+
+        if let Some((position, _)) = stops.iter().find(|(p, _)| !(0.0..=1.0).contains(p)) {
+            return Err(format!("Gradient stop out of range: {}", position));
+        }
+
+        // Stable, so stops sharing a position keep their order and make a hard edge
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(Gradient { stops })
+    }
+
+    /// Colors spread evenly from 0.0 to 1.0.
+    pub fn even(colors: Vec<Rgb<u8>>) -> Result<Self, String> {
+        let steps = (colors.len().max(2) - 1) as f64;
+
+        Gradient::new(
+            colors
+                .into_iter()
+                .enumerate()
+                .map(|(i, color)| (i as f64 / steps, color))
+                .collect(),
+        )
+    }
+
+    /// Shadows to one color, highlights to another.
+    pub fn duotone(shadows: Rgb<u8>, highlights: Rgb<u8>) -> Self {
+        Gradient {
+            stops: vec![(0.0, shadows), (1.0, highlights)],
+        }
+    }
+
+    /// Shadows, midtones and highlights to three colors.
+    pub fn tritone(shadows: Rgb<u8>, midtones: Rgb<u8>, highlights: Rgb<u8>) -> Self {
+        Gradient {
+            stops: vec![(0.0, shadows), (0.5, midtones), (1.0, highlights)],
+        }
+    }
+
+    pub fn stops(&self) -> &[(f64, Rgb<u8>)] {
+        &self.stops
+    }
+
+    /// The color at a position of the gradient.
+    pub fn sample(&self, t: f64, space: GradientSpace) -> Rgb<u8> {
+        let t = t.clamp(0.0, 1.0);
+
+        let next = self.stops.partition_point(|(position, _)| *position <= t);
+
+        if next == 0 {
+            return self.stops[0].1;
+        }
+        if next == self.stops.len() {
+            return self.stops[next - 1].1;
+        }
+
+        let (p0, c0) = self.stops[next - 1];
+        let (p1, c1) = self.stops[next];
+
+        let f = (t - p0) / (p1 - p0);
+        let (a, b) = (space.encode(c0), space.encode(c1));
+
+        space.decode([0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * f))
+    }
+}
+
+/// Clap FromStr. Either the name of a preset, hex colors spread evenly ("#000000 #ff8800 #ffffff")
+/// or position:color stops ("0:#000000 0.3:#ff8800 1:#ffffff").
+///
+/// Duotone presets: sepia, cyanotype, noir, purple-gold. Tritone presets: sunset, fire, ocean.
+impl FromStr for Gradient {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = |hex: &str| {
+            hex_to_rgb(hex)
+                .map(|(r, g, b)| Rgb([r, g, b]))
+                .ok_or(format!("Invalid gradient color: {}", hex))
+        };
+
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "sepia" => return Ok(Gradient::duotone(Rgb([43, 25, 10]), Rgb([245, 226, 190]))),
+            "cyanotype" => return Ok(Gradient::duotone(Rgb([0, 33, 85]), Rgb([222, 240, 255]))),
+            "noir" => return Ok(Gradient::duotone(Rgb([10, 10, 20]), Rgb([235, 235, 225]))),
+            "purple-gold" => return Ok(Gradient::duotone(Rgb([61, 16, 89]), Rgb([255, 200, 60]))),
+            "sunset" => {
+                return Ok(Gradient::tritone(
+                    Rgb([40, 12, 72]),
+                    Rgb([226, 73, 78]),
+                    Rgb([255, 214, 130]),
+                ))
+            }
+            "fire" => {
+                return Ok(Gradient::tritone(
+                    Rgb([0, 0, 0]),
+                    Rgb([220, 40, 0]),
+                    Rgb([255, 240, 120]),
+                ))
+            }
+            "ocean" => {
+                return Ok(Gradient::tritone(
+                    Rgb([2, 16, 40]),
+                    Rgb([0, 110, 140]),
+                    Rgb([200, 245, 240]),
+                ))
+            }
+            _ => {}
+        }
+
+        let entries: Vec<&str> = s.split_whitespace().collect();
+
+        if entries.iter().all(|entry| entry.contains(':')) {
+            let stops = entries
+                .iter()
+                .map(|entry| {
+                    let (position, color) = entry.split_once(':').unwrap();
+                    let position = position
+                        .parse::<f64>()
+                        .map_err(|_| format!("Invalid gradient stop: {}", entry))?;
+
+                    Ok((position, hex(color)?))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+
+            Gradient::new(stops)
+        } else {
+            Gradient::even(
+                entries
+                    .iter()
+                    .map(|entry| hex(entry))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+    }
+}
+
+/// Number of precomputed gradient samples used by gradient_map.
+const GRADIENT_SAMPLES: usize = 1024;
+
+/// Gradient map. Maps a property of each pixel, luminance by default, through a color gradient.
+/// Alpha is passed through.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings remapping the channels before the key is read.
+/// * `key` - The property picking the position on the gradient, see FilterParam. None is
+///   luminance.
+/// * `gradient` - The colors to map to.
+/// * `space` - The color space the gradient is interpolated in.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn gradient_map(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    key: Option<FilterParam>,
+    gradient: &Gradient,
+    space: GradientSpace,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let key = key.unwrap_or(FilterParam::Luminance);
+
+    let samples: Vec<Rgb<u8>> = (0..GRADIENT_SAMPLES)
+        .map(|i| gradient.sample(i as f64 / (GRADIENT_SAMPLES - 1) as f64, space))
+        .collect();
+
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let remapped = match lhs {
+            Some(ref lhs) => Rgba([
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+                in_pixel[3],
+            ]),
+            None => in_pixel,
+        };
+
+        let t = key.normalised(&remapped).clamp(0.0, 1.0);
+        let color = samples[(t * (GRADIENT_SAMPLES - 1) as f64).round() as usize];
+
+        let out = Rgba([color.0[0], color.0[1], color.0[2], in_pixel[3]]);
+
+        *pixel = mix_pixels(remapped, out, mix);
+    });
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn red_to_blue() -> Gradient {
+        Gradient::duotone(Rgb([255, 0, 0]), Rgb([0, 0, 255]))
+    }
+
+    #[test]
+    fn test_sample_spaces() {
+        let gradient = red_to_blue();
+
+        assert_eq!(gradient.sample(0.0, GradientSpace::Oklab), Rgb([255, 0, 0]));
+        assert_eq!(gradient.sample(1.0, GradientSpace::Oklab), Rgb([0, 0, 255]));
+
+        assert_eq!(
+            gradient.sample(0.5, GradientSpace::Srgb),
+            Rgb([128, 0, 128])
+        );
+        assert_eq!(
+            gradient.sample(0.5, GradientSpace::Linear),
+            Rgb([188, 0, 188])
+        );
+
+        let oklab = gradient.sample(0.5, GradientSpace::Oklab);
+        assert_ne!(oklab, Rgb([128, 0, 128]));
+        assert_ne!(oklab, Rgb([188, 0, 188]));
+    }
+
+    #[test]
+    fn test_oklab_round_trip() {
+        for color in [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [0.2, 0.5, 0.9]] {
+            let back = oklab_to_linear_srgb(linear_srgb_to_oklab(color));
+            for i in 0..3 {
+                assert!((back[i] - color[i]).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let even: Gradient = "#000000 #808080 ffffff".parse().unwrap();
+        assert_eq!(even.stops()[1], (0.5, Rgb([128, 128, 128])));
+
+        let stops: Gradient = "1:#ffffff 0.25:#ff0000 0:#000000".parse().unwrap();
+        assert_eq!(stops.stops()[1], (0.25, Rgb([255, 0, 0])));
+        assert_eq!(stops.sample(0.1, GradientSpace::Srgb), Rgb([102, 0, 0]));
+
+        assert!("sepia".parse::<Gradient>().is_ok());
+        assert!("2:#ffffff".parse::<Gradient>().is_err());
+        assert!("#fffff".parse::<Gradient>().is_err());
+        assert!("#é€a #000000".parse::<Gradient>().is_err());
+    }
+
+    #[test]
+    fn test_gradient_map() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([0, 0, 0, 10])
+            } else {
+                Rgba([255, 255, 255, 20])
+            }
+        }));
+
+        let out = gradient_map(img, None, None, &red_to_blue(), GradientSpace::Srgb, None);
+
+        assert_eq!(*out.get_pixel(0, 0), Rgba([255, 0, 0, 10]));
+        assert_eq!(*out.get_pixel(1, 0), Rgba([0, 0, 255, 20]));
+    }
+
+    #[test]
+    fn test_gradient_map_key() {
+        // Pure green is at a third of the hue wheel
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba([0, 255, 0, 255])));
+        let gradient: Gradient = "#000000 #ffffff".parse().unwrap();
+
+        let out = gradient_map(
+            img.clone(),
+            None,
+            Some(FilterParam::Hue),
+            &gradient,
+            GradientSpace::Srgb,
+            None,
+        );
+        assert_eq!(*out.get_pixel(0, 0), Rgba([85, 85, 85, 255]));
+
+        // Remapped so the blue channel is read as red
+        let out = gradient_map(
+            img,
+            Some(vec!["b".to_string(), "b".to_string(), "b".to_string()]),
+            Some(FilterParam::Red),
+            &gradient,
+            GradientSpace::Srgb,
+            None,
+        );
+        assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+    }
+}
@@ -4,11 +4,31 @@
 //! operations (bloom, blending modes, pixel sorting) for images.
 //!
 //! Functions:
-//! - add, sub, mult, div
-//! - bitshift left | right
+//! - add, sub, mult, div, pow
+//! - modulo, min_channels, max_channels, absdiff, avg_weighted, with a color or an image operand
+//! - bitshift left | right, bit rotation, bit reversal, Gray code, 24-bit word shifts
 //! - and, or, xor (and their complements with -n flag)
+//! - bit plane extraction, swapping and reordering
 //! - screen, overlay, average
+//! - multiply, darken, lighten, dodge, burn, light and the other separable blend modes
+//! - blend_hue, blend_saturation, blend_color, blend_luminosity
+//! - Porter-Duff compositing of one image onto another
+//! - greyscale by Rec.601, Rec.709, average, lightness, one channel, custom weights or Lab L*
+//! - posterize, threshold (global or Otsu) and levels
+//! - tone curves with monotone spline control points, per channel and master
+//! - .cube 1D and 3D LUTs, trilinear or tetrahedral, and baking op chains into LUTs
+//! - gradient maps, duotone and tritone, interpolated in sRGB, linear light or Oklab
+//! - convolution with custom or separable kernels: box, gaussian, sharpen, unsharp mask, emboss,
+//!   Laplacian, and Sobel, Prewitt and Scharr edge detection
+//! - median, erode, dilate, opening, closing and morphological gradient, on images or filter masks
+//! - pixelate, hexagonal, triangular and Voronoi mosaics, optionally through a mask
 //! - bloom
+//! - channel mixer matrices, with sepia, swap, desaturate and color blindness presets
+//! - color quantization (median cut, k-means, octree) and palette mapping, hex and GIMP palettes
+//! - dithering: Floyd-Steinberg, Atkinson, JJN, Sierra, Bayer and blue noise, to a bit depth or palette
+//! - RGB split and lens chromatic aberration
+//! - displacement map warps from an image or seeded noise
+//! - LSB steganography, embedding and extracting byte payloads
 //!
 //! All functions support operand reordering, allowing precise control over how the image's color channels
 //! are processed. Operand reordering lets you redefine how the source image's R, G, and B channels
@@ -27,20 +47,48 @@
 //!
 //! Future developments:
 //! - Pixel sorting by some condition like hue, luminance, etc
-//! - Option to make bloom faster for use in videos
+
+// The load_image and get_color_from_control test helpers predate running clippy on tests
+#![cfg_attr(test, allow(clippy::let_and_return, clippy::needless_return))]
 
 pub mod arithmetic;
 pub mod bitwise;
 pub mod blend;
+pub mod chromatic;
+pub mod composite;
+pub mod convolve;
+pub mod curves;
+pub mod displace;
+pub mod dither;
 pub mod filter;
 pub mod functions;
+pub mod gradient;
+pub mod lut;
+pub mod mixer;
+pub mod morphology;
+pub mod mosaic;
+pub mod palette;
 pub mod sort;
+pub mod stego;
 pub mod utils;
 
 pub use arithmetic::*;
 pub use bitwise::*;
 pub use blend::*;
+pub use chromatic::*;
+pub use composite::*;
+pub use convolve::*;
+pub use curves::*;
+pub use displace::*;
+pub use dither::*;
 pub use filter::*;
 pub use functions::*;
+pub use gradient::*;
+pub use lut::*;
+pub use mixer::*;
+pub use morphology::*;
+pub use mosaic::*;
+pub use palette::*;
 pub use sort::*;
+pub use stego::*;
 pub use utils::*;
@@ -0,0 +1,462 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::utils::{mix_pixels, Mix};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Whether a CubeLut maps each channel on its own or maps whole colors.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LutDimension {
+    /// `size` entries, each channel of an entry is the curve of that channel.
+    One,
+    /// A `size`^3 lattice of colors, red changing fastest, then green, then blue.
+    Three,
+}
+
+/// A color lookup table in the Adobe / Resolve `.cube` format.
+///
+/// Values are normalised, 0.0-1.0 covering the 0-255 channel range. DOMAIN_MIN and DOMAIN_MAX
+/// give the input range the table spans, inputs outside of it are clamped. Resolve's
+/// LUT_1D_INPUT_RANGE and LUT_3D_INPUT_RANGE are read as a domain shared by all channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    pub dimension: LutDimension,
+    pub size: usize,
+    pub domain_min: [f64; 3],
+    pub domain_max: [f64; 3],
+    pub table: Vec<[f64; 3]>,
+}
+
+impl CubeLut {
+    /// Parse the text of a `.cube` file.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut title = None;
+        let mut dimension = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table: Vec<[f64; 3]> = vec![];
+
+        let floats = |values: &[&str], line: &str| -> Result<Vec<f64>, String> {
+            values
+                .iter()
+                .map(|value| value.parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("Invalid .cube line: {}", line))
+        };
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            match words[0] {
+                "TITLE" => {
+                    title = Some(line["TITLE".len()..].trim().trim_matches('"').to_string());
+                }
+                "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                    if dimension.is_some() {
+                        return Err("More than one LUT size in .cube file".to_string());
+                    }
+
+                    let size = words
+                        .get(1)
+                        .and_then(|size| size.parse::<usize>().ok())
+                        .filter(|size| *size >= 2)
+                        .ok_or(format!("Invalid .cube size: {}", line))?;
+
+                    dimension = Some(match words[0] {
+                        "LUT_1D_SIZE" => (LutDimension::One, size),
+                        _ => (LutDimension::Three, size),
+                    });
+                }
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let values = floats(&words[1..], line)?;
+                    let values: [f64; 3] = values
+                        .try_into()
+                        .map_err(|_| format!("Invalid .cube domain: {}", line))?;
+
+                    match words[0] {
+                        "DOMAIN_MIN" => domain_min = values,
+                        _ => domain_max = values,
+                    }
+                }
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => match floats(&words[1..], line)?[..]
+                {
+                    [min, max] => {
+                        domain_min = [min; 3];
+                        domain_max = [max; 3];
+                    }
+                    _ => return Err(format!("Invalid .cube input range: {}", line)),
+                },
+                keyword if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    // Unknown keywords from other tools are skipped
+                }
+                _ => match floats(&words, line)?[..] {
+                    [r, g, b] => table.push([r, g, b]),
+                    _ => return Err(format!("Invalid .cube entry: {}", line)),
+                },
+            }
+        }
+
+        let (dimension, size) = dimension.ok_or("Missing LUT_1D_SIZE or LUT_3D_SIZE")?;
+
+        let expected = match dimension {
+            LutDimension::One => size,
+            LutDimension::Three => size * size * size,
+        };
+        if table.len() != expected {
+            return Err(format!(
+                "Expected {} .cube entries, found {}",
+                expected,
+                table.len()
+            ));
+        }
+
+        if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+            return Err("DOMAIN_MAX must be above DOMAIN_MIN".to_string());
+        }
+
+        Ok(CubeLut {
+            title,
+            dimension,
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    /// Position of a channel value in table steps, 0.0 to size - 1.
+    fn position(&self, channel: usize, value: u8) -> f64 {
+        let (min, max) = (self.domain_min[channel], self.domain_max[channel]);
+        let t = ((value as f64 / 255.0 - min) / (max - min)).clamp(0.0, 1.0);
+
+        t * (self.size - 1) as f64
+    }
+
+    fn lattice(&self, r: usize, g: usize, b: usize) -> [f64; 3] {
+        self.table[r + g * self.size + b * self.size * self.size]
+    }
+
+    /// Look up a color, returning normalised RGB.
+    pub fn lookup(&self, color: [u8; 3], interpolation: LutInterpolation) -> [f64; 3] {
+        let position = [0, 1, 2].map(|i| self.position(i, color[i]));
+
+        // Lower lattice index and fractional part of each channel
+        let split = position.map(|p| {
+            let index = (p.floor() as usize).min(self.size - 2);
+            (index, p - index as f64)
+        });
+
+        match self.dimension {
+            LutDimension::One => [0, 1, 2].map(|i| {
+                let (index, t) = split[i];
+                let (a, b) = (self.table[index][i], self.table[index + 1][i]);
+                a + (b - a) * t
+            }),
+            LutDimension::Three => {
+                let [(r, fr), (g, fg), (b, fb)] = split;
+                let c = |dr: usize, dg: usize, db: usize| self.lattice(r + dr, g + dg, b + db);
+
+                match interpolation {
+                    LutInterpolation::Trilinear => {
+                        let lerp = |a: [f64; 3], b: [f64; 3], t: f64| {
+                            [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t)
+                        };
+
+                        let c00 = lerp(c(0, 0, 0), c(1, 0, 0), fr);
+                        let c10 = lerp(c(0, 1, 0), c(1, 1, 0), fr);
+                        let c01 = lerp(c(0, 0, 1), c(1, 0, 1), fr);
+                        let c11 = lerp(c(0, 1, 1), c(1, 1, 1), fr);
+
+                        lerp(lerp(c00, c10, fg), lerp(c01, c11, fg), fb)
+                    }
+                    LutInterpolation::Tetrahedral => {
+                        // Walk from the lowest to the highest corner of the cell, one axis at a
+                        // time in order of the largest fraction
+                        let (t, [ca, cb]) = if fr >= fg && fg >= fb {
+                            ((fr, fg, fb), [c(1, 0, 0), c(1, 1, 0)])
+                        } else if fr >= fb && fb >= fg {
+                            ((fr, fb, fg), [c(1, 0, 0), c(1, 0, 1)])
+                        } else if fb >= fr && fr >= fg {
+                            ((fb, fr, fg), [c(0, 0, 1), c(1, 0, 1)])
+                        } else if fg >= fr && fr >= fb {
+                            ((fg, fr, fb), [c(0, 1, 0), c(1, 1, 0)])
+                        } else if fg >= fb && fb >= fr {
+                            ((fg, fb, fr), [c(0, 1, 0), c(0, 1, 1)])
+                        } else {
+                            ((fb, fg, fr), [c(0, 0, 1), c(0, 1, 1)])
+                        };
+
+                        let (t0, t1, t2) = t;
+                        let (c000, c111) = (c(0, 0, 0), c(1, 1, 1));
+
+                        [0, 1, 2].map(|i| {
+                            c000[i]
+                                + t0 * (ca[i] - c000[i])
+                                + t1 * (cb[i] - ca[i])
+                                + t2 * (c111[i] - cb[i])
+                        })
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Clap FromStr, the text of a `.cube` file.
+impl FromStr for CubeLut {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CubeLut::parse(s)
+    }
+}
+
+/// Writes the LUT as a `.cube` file.
+impl fmt::Display for CubeLut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(title) = &self.title {
+            writeln!(f, "TITLE \"{}\"", title)?;
+        }
+
+        match self.dimension {
+            LutDimension::One => writeln!(f, "LUT_1D_SIZE {}", self.size)?,
+            LutDimension::Three => writeln!(f, "LUT_3D_SIZE {}", self.size)?,
+        }
+
+        let [r, g, b] = self.domain_min;
+        writeln!(f, "DOMAIN_MIN {:.6} {:.6} {:.6}", r, g, b)?;
+        let [r, g, b] = self.domain_max;
+        writeln!(f, "DOMAIN_MAX {:.6} {:.6} {:.6}", r, g, b)?;
+        writeln!(f)?;
+
+        for [r, g, b] in self.table.iter() {
+            writeln!(f, "{:.6} {:.6} {:.6}", r, g, b)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How colors between the lattice points of a 3D LUT are interpolated. 1D LUTs are always
+/// interpolated linearly.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LutInterpolation {
+    /// Blend the 8 corners of the lattice cell.
+    Trilinear,
+    /// Blend the 4 corners of the tetrahedron holding the color. Sharper, and keeps greys on the
+    /// grey axis of the LUT.
+    Tetrahedral,
+}
+
+/// Clap FromStr
+impl FromStr for LutInterpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "trilinear" => Ok(LutInterpolation::Trilinear),
+            "tetrahedral" => Ok(LutInterpolation::Tetrahedral),
+
+            _ => Err(format!("Invalid LutInterpolation name: {}", s)),
+        }
+    }
+}
+
+/// Apply a `.cube` LUT to every pixel. Alpha is passed through.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lut` - The parsed LUT.
+/// * `interpolation` - How 3D LUTs are interpolated between lattice points.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn apply_cube_lut(
+    img: DynamicImage,
+    lut: &CubeLut,
+    interpolation: LutInterpolation,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let color = lut.lookup([in_pixel[0], in_pixel[1], in_pixel[2]], interpolation);
+        let color = color.map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8);
+
+        let out = Rgba([color[0], color[1], color[2], in_pixel[3]]);
+
+        *pixel = mix_pixels(in_pixel, out, mix);
+    });
+
+    output
+}
+
+/// Bake a chain of per-pixel operations into a 3D `.cube` LUT.
+///
+/// The chain is run once on an image holding every lattice color, so it must only depend on
+/// each pixel's own color: blurs, bloom, sorting and other spatial operations can't be baked.
+/// Lattice colors are rounded to 8 bits before the chain sees them, sizes where 255 / (size - 1)
+/// is whole (2, 4, 6, 16, 18, 52, 86) avoid that rounding.
+/// * `size` - Lattice points per channel, 2-256. 17, 33 and 65 are common.
+/// * `title` - Optional TITLE of the LUT.
+/// * `chain` - The operations, e.g. `|img| channel_mixer(img, ChannelMatrix::sepia(), false, None)`.
+pub fn bake_cube_lut(
+    size: usize,
+    title: Option<String>,
+    chain: impl Fn(DynamicImage) -> RgbaImage,
+) -> CubeLut {
+    assert!((2..=256).contains(&size), "Invalid LUT size: {}", size);
+
+    let level = |i: u32| (i as f64 * 255.0 / (size - 1) as f64).round() as u8;
+
+    // Red along x, green and blue along y, so pixels are in .cube order
+    let lattice: RgbaImage = ImageBuffer::from_fn(size as u32, (size * size) as u32, |x, y| {
+        Rgba([
+            level(x),
+            level(y % size as u32),
+            level(y / size as u32),
+            255,
+        ])
+    });
+
+    let output = chain(DynamicImage::ImageRgba8(lattice));
+
+    let table = output
+        .pixels()
+        .map(|pixel| [0, 1, 2].map(|i| pixel[i] as f64 / 255.0))
+        .collect();
+
+    CubeLut {
+        title,
+        dimension: LutDimension::Three,
+        size,
+        domain_min: [0.0; 3],
+        domain_max: [1.0; 3],
+        table,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mixer::{channel_mixer, ChannelMatrix};
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn load_lut(file_name: &str) -> CubeLut {
+        let mut path = env::current_dir().expect("Failed to get current directory");
+        path.push("assets/luts/");
+        path.push(file_name);
+
+        let text: String = fs::read_to_string::<PathBuf>(path).expect("Failed to read LUT.");
+        text.parse().expect("Failed to parse LUT.")
+    }
+
+    fn colors() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(16, 16, |x, y| {
+            Rgba([(x * 17) as u8, (y * 17) as u8, (x * y) as u8, 99])
+        }))
+    }
+
+    #[test]
+    fn test_parse() {
+        let lut = load_lut("identity_3d_2.cube");
+
+        assert_eq!(lut.title, Some("Identity".to_string()));
+        assert_eq!(lut.dimension, LutDimension::Three);
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.table[1], [1.0, 0.0, 0.0]);
+
+        let lut = load_lut("half_domain_1d_2.cube");
+        assert_eq!(lut.dimension, LutDimension::One);
+        assert_eq!(lut.domain_max, [0.5; 3]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(CubeLut::parse("0 0 0").is_err());
+        assert!(CubeLut::parse("LUT_3D_SIZE 2\n0 0 0\n1 1 1").is_err());
+        assert!(CubeLut::parse("LUT_1D_SIZE 2\n0 0 0\n1 1").is_err());
+        assert!(CubeLut::parse("LUT_1D_SIZE 2\nDOMAIN_MAX 0 0 0\n0 0 0\n1 1 1").is_err());
+    }
+
+    #[test]
+    fn test_identity() {
+        let lut = load_lut("identity_3d_2.cube");
+
+        for interpolation in [LutInterpolation::Trilinear, LutInterpolation::Tetrahedral] {
+            let out = apply_cube_lut(colors(), &lut, interpolation, None);
+            assert_eq!(out, colors().to_rgba8());
+        }
+    }
+
+    #[test]
+    fn test_1d() {
+        let out = apply_cube_lut(
+            colors(),
+            &load_lut("invert_1d_3.cube"),
+            LutInterpolation::Trilinear,
+            None,
+        );
+        assert_eq!(*out.get_pixel(3, 5), Rgba([204, 170, 240, 99]));
+
+        // Inputs past DOMAIN_MAX 0.5 clamp to white
+        let out = apply_cube_lut(
+            colors(),
+            &load_lut("half_domain_1d_2.cube"),
+            LutInterpolation::Trilinear,
+            None,
+        );
+        assert_eq!(*out.get_pixel(3, 10), Rgba([102, 255, 60, 99]));
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let lut = load_lut("white_to_black_3d_2.cube");
+        let grey = [128, 128, 128];
+
+        // Trilinear pulls in all eight corners, t - t^3 on every channel
+        let out = lut.lookup(grey, LutInterpolation::Trilinear);
+        assert_eq!((out[0] * 255.0).round(), 96.0);
+
+        // Tetrahedral only follows the grey diagonal, which goes from black to black
+        let out = lut.lookup(grey, LutInterpolation::Tetrahedral);
+        assert!(out.iter().all(|channel| channel.abs() < 1e-9));
+
+        // Both agree off the diagonal cell corners
+        for interpolation in [LutInterpolation::Trilinear, LutInterpolation::Tetrahedral] {
+            let out = lut.lookup([255, 0, 255], interpolation);
+            assert_eq!(out, [1.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn test_bake_round_trip() {
+        let chain = |img: DynamicImage| {
+            channel_mixer(img, ChannelMatrix::swap("r", "g").unwrap(), false, None)
+        };
+
+        let lut = bake_cube_lut(18, Some("Swap".to_string()), chain);
+        assert_eq!(lut.table.len(), 18 * 18 * 18);
+
+        // Through the text format and back
+        let lut: CubeLut = lut.to_string().parse().unwrap();
+        assert_eq!(lut.title, Some("Swap".to_string()));
+
+        let expected = chain(colors());
+        for interpolation in [LutInterpolation::Trilinear, LutInterpolation::Tetrahedral] {
+            assert_eq!(
+                apply_cube_lut(colors(), &lut, interpolation, None),
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::str::FromStr;
+
+use crate::utils::{linear_to_srgb, mix_pixels, srgb_to_linear, Channel, Mix};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// A 4x5 color matrix. Each row computes one output channel (R, G, B, A) as a weighted sum of the
+/// input R, G, B and A, plus a constant offset in the last column. Channels are normalised to
+/// 0.0-1.0, so an offset of 0.5 adds half of full brightness.
+///
+/// Float matrices are used as is. Integer matrices are in percent, so 100 is a weight of 1.0 and
+/// an offset of 50 adds half of full brightness. 3x4 matrices leave alpha untouched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelMatrix(pub [[f64; 5]; 4]);
+
+impl ChannelMatrix {
+    pub const IDENTITY: ChannelMatrix = ChannelMatrix([
+        [1.0, 0.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0, 0.0],
+    ]);
+
+    /// Build a matrix from 3x3 RGB weights, without offsets and with alpha untouched.
+    fn from_rgb_weights(weights: [[f64; 3]; 3]) -> Self {
+        let mut matrix = Self::IDENTITY;
+
+        for (row, weights) in matrix.0.iter_mut().zip(weights.iter()) {
+            row[..3].copy_from_slice(weights);
+        }
+
+        matrix
+    }
+
+    /// The classic sepia tone.
+    pub fn sepia() -> Self {
+        Self::from_rgb_weights([
+            [0.393, 0.769, 0.189],
+            [0.349, 0.686, 0.168],
+            [0.272, 0.534, 0.131],
+        ])
+    }
+
+    /// Greyscale using the Rec. 709 luminance weights, the same as `calc_luminance`.
+    pub fn desaturate() -> Self {
+        let luminance = [0.2126, 0.7152, 0.0722];
+        Self::from_rgb_weights([luminance; 3])
+    }
+
+    /// Swap two of the R, G and B channels, named "r", "g" or "b".
+    pub fn swap(a: &str, b: &str) -> Result<Self, String> {
+        let (a, b) = (a.parse::<Channel>()?, b.parse::<Channel>()?);
+
+        let mut matrix = Self::IDENTITY;
+        matrix.0.swap(a.index(), b.index());
+        Ok(matrix)
+    }
+
+    /// Simulates protanopia (no red cones), from Machado, Oliveira and Fernandes 2009. Meant to be
+    /// applied in linear light.
+    pub fn protanopia() -> Self {
+        Self::from_rgb_weights([
+            [0.152286, 1.052583, -0.204868],
+            [0.114503, 0.786281, 0.099216],
+            [-0.003882, -0.048116, 1.051998],
+        ])
+    }
+
+    /// Simulates deuteranopia (no green cones), from Machado, Oliveira and Fernandes 2009. Meant to
+    /// be applied in linear light.
+    pub fn deuteranopia() -> Self {
+        Self::from_rgb_weights([
+            [0.367322, 0.860646, -0.227968],
+            [0.280085, 0.672501, 0.047413],
+            [-0.011820, 0.042940, 0.968881],
+        ])
+    }
+
+    /// Simulates tritanopia (no blue cones), from Machado, Oliveira and Fernandes 2009. Meant to be
+    /// applied in linear light.
+    pub fn tritanopia() -> Self {
+        Self::from_rgb_weights([
+            [1.255528, -0.076749, -0.178779],
+            [-0.078411, 0.930809, 0.147602],
+            [0.004733, 0.691367, 0.303900],
+        ])
+    }
+
+    /// Multiply a normalised RGBA color by the matrix.
+    pub fn apply(&self, c: [f64; 4]) -> [f64; 4] {
+        self.0
+            .map(|row| row[0] * c[0] + row[1] * c[1] + row[2] * c[2] + row[3] * c[3] + row[4])
+    }
+}
+
+impl From<[[f64; 5]; 4]> for ChannelMatrix {
+    fn from(matrix: [[f64; 5]; 4]) -> Self {
+        ChannelMatrix(matrix)
+    }
+}
+
+impl From<[[f64; 4]; 3]> for ChannelMatrix {
+    fn from(matrix: [[f64; 4]; 3]) -> Self {
+        let mut out = Self::IDENTITY;
+
+        for (row, weights) in out.0.iter_mut().zip(matrix.iter()) {
+            *row = [weights[0], weights[1], weights[2], 0.0, weights[3]];
+        }
+
+        out
+    }
+}
+
+impl From<[[i32; 5]; 4]> for ChannelMatrix {
+    fn from(matrix: [[i32; 5]; 4]) -> Self {
+        ChannelMatrix(matrix.map(|row| row.map(|v| v as f64 / 100.0)))
+    }
+}
+
+impl From<[[i32; 4]; 3]> for ChannelMatrix {
+    fn from(matrix: [[i32; 4]; 3]) -> Self {
+        ChannelMatrix::from(matrix.map(|row| row.map(|v| v as f64 / 100.0)))
+    }
+}
+
+/// Clap FromStr, the name of a preset.
+impl FromStr for ChannelMatrix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "identity" => Ok(ChannelMatrix::IDENTITY),
+            "sepia" => Ok(ChannelMatrix::sepia()),
+            "desaturate" => Ok(ChannelMatrix::desaturate()),
+            "swap-rg" => ChannelMatrix::swap("r", "g"),
+            "swap-rb" => ChannelMatrix::swap("r", "b"),
+            "swap-gb" => ChannelMatrix::swap("g", "b"),
+            "protanopia" => Ok(ChannelMatrix::protanopia()),
+            "deuteranopia" => Ok(ChannelMatrix::deuteranopia()),
+            "tritanopia" => Ok(ChannelMatrix::tritanopia()),
+
+            _ => Err(format!("Invalid ChannelMatrix preset: {}", s)),
+        }
+    }
+}
+
+/// Channel mixer. Multiplies every pixel by a color matrix, letting each output channel be any
+/// blend of the input channels.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `matrix` - The color matrix, a ChannelMatrix or anything that converts into one.
+/// * `linear` - If true, RGB is converted to linear light before the matrix and back after. Use
+///   this for the color blindness presets.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn channel_mixer(
+    img: DynamicImage,
+    matrix: impl Into<ChannelMatrix>,
+    linear: bool,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let matrix: ChannelMatrix = matrix.into();
+
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let mut c = in_pixel.0.map(|channel| channel as f64 / 255.0);
+        if linear {
+            for channel in c.iter_mut().take(3) {
+                *channel = srgb_to_linear(*channel);
+            }
+        }
+
+        let mut c = matrix.apply(c);
+        if linear {
+            for channel in c.iter_mut().take(3) {
+                *channel = linear_to_srgb(channel.clamp(0.0, 1.0));
+            }
+        }
+
+        let out = c.map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8);
+
+        *pixel = mix_pixels(in_pixel, Rgba(out), mix);
+    });
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_images::solid;
+
+    #[test]
+    fn test_identity() {
+        let img = solid(1, 1, Rgba([12, 34, 56, 78]));
+
+        let out = channel_mixer(img.clone(), ChannelMatrix::IDENTITY, false, None);
+        assert_eq!(out, img.to_rgba8());
+
+        let out = channel_mixer(img.clone(), ChannelMatrix::IDENTITY, true, None);
+        assert_eq!(out, img.to_rgba8());
+    }
+
+    #[test]
+    fn test_sepia() {
+        let out = channel_mixer(
+            solid(1, 1, Rgba([100, 100, 100, 255])),
+            "sepia".parse::<ChannelMatrix>().unwrap(),
+            false,
+            None,
+        );
+
+        assert_eq!(*out.get_pixel(0, 0), Rgba([135, 120, 94, 255]));
+    }
+
+    #[test]
+    fn test_swap_and_desaturate() {
+        let img = solid(1, 1, Rgba([255, 0, 10, 255]));
+
+        let out = channel_mixer(
+            img.clone(),
+            ChannelMatrix::swap("r", "b").unwrap(),
+            false,
+            None,
+        );
+        assert_eq!(*out.get_pixel(0, 0), Rgba([10, 0, 255, 255]));
+
+        let out = channel_mixer(img, ChannelMatrix::desaturate(), false, None);
+        assert_eq!(*out.get_pixel(0, 0), Rgba([55, 55, 55, 255]));
+        assert!(ChannelMatrix::swap("r", "a").is_err());
+    }
+
+    #[test]
+    fn test_integer_matrix() {
+        let img = solid(1, 1, Rgba([200, 100, 50, 255]));
+
+        // Half the red into green, plus a 20% offset on blue, alpha at 40%
+        let matrix: [[i32; 5]; 4] = [
+            [100, 0, 0, 0, 0],
+            [50, 0, 0, 0, 0],
+            [0, 0, 100, 0, 20],
+            [0, 0, 0, 40, 0],
+        ];
+
+        let out = channel_mixer(img.clone(), matrix, false, None);
+        assert_eq!(*out.get_pixel(0, 0), Rgba([200, 100, 101, 102]));
+
+        let float: [[f64; 4]; 3] = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.5, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.2],
+        ];
+        let out = channel_mixer(img, float, false, None);
+        assert_eq!(*out.get_pixel(0, 0), Rgba([200, 100, 101, 255]));
+    }
+
+    #[test]
+    fn test_color_blindness_keeps_greys() {
+        let img = solid(1, 1, Rgba([128, 128, 128, 255]));
+
+        for matrix in [
+            ChannelMatrix::protanopia(),
+            ChannelMatrix::deuteranopia(),
+            ChannelMatrix::tritanopia(),
+        ] {
+            let out = channel_mixer(img.clone(), matrix, true, None);
+            assert_eq!(*out.get_pixel(0, 0), Rgba([128, 128, 128, 255]));
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use std::str::FromStr;
+
+use crate::utils::{mix_pixels, EdgeMode, Mix};
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// The neighbourhood a rank or morphological filter looks at around each pixel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StructuringElement {
+    /// A (2 * radius + 1) square.
+    Square(u32),
+    /// A disc of the given radius.
+    Circle(u32),
+}
+
+/// Clap FromStr, "square:radius" or "circle:radius".
+impl FromStr for StructuringElement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (shape, radius) = s
+            .split_once(':')
+            .ok_or(format!("Invalid StructuringElement: {}", s))?;
+
+        let radius = radius
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid StructuringElement radius: {}", radius))?;
+
+        match shape.trim().to_lowercase().as_str() {
+            "square" => Ok(StructuringElement::Square(radius)),
+            "circle" | "disc" => Ok(StructuringElement::Circle(radius)),
+
+            _ => Err(format!("Invalid StructuringElement shape: {}", shape)),
+        }
+    }
+}
+
+impl StructuringElement {
+    /// Horizontal half width of every row of the element, from -radius to radius.
+    fn spans(&self) -> Vec<(i64, i64)> {
+        match *self {
+            StructuringElement::Square(radius) => {
+                let r = radius as i64;
+                (-r..=r).map(|dy| (dy, r)).collect()
+            }
+            StructuringElement::Circle(radius) => {
+                let r = radius as i64;
+                // The + 0.5 rounds the disc out so small radii aren't reduced to a cross
+                let limit = (radius as f64 + 0.5).powi(2);
+                (-r..=r)
+                    .map(|dy| (dy, (limit - (dy * dy) as f64).sqrt().floor() as i64))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Morphological and rank operations.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MorphOp {
+    /// Median of the neighbourhood, removes salt and pepper noise while keeping edges.
+    Median,
+    /// Minimum of the neighbourhood, shrinks bright areas.
+    Erode,
+    /// Maximum of the neighbourhood, grows bright areas.
+    Dilate,
+    /// Erode then dilate, removes bright specks smaller than the element.
+    Open,
+    /// Dilate then erode, fills dark holes smaller than the element.
+    Close,
+    /// Dilate minus erode, the outline of shapes.
+    Gradient,
+}
+
+/// Clap FromStr
+impl FromStr for MorphOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "median" => Ok(MorphOp::Median),
+            "erode" | "min" => Ok(MorphOp::Erode),
+            "dilate" | "max" => Ok(MorphOp::Dilate),
+            "open" | "opening" => Ok(MorphOp::Open),
+            "close" | "closing" => Ok(MorphOp::Close),
+            "gradient" => Ok(MorphOp::Gradient),
+
+            _ => Err(format!("Invalid MorphOp name: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Rank {
+    Min,
+    Max,
+    Median,
+}
+
+/// Rank filter of one channel plane using Huang's sliding histogram. Each row starts with the
+/// histogram of its first neighbourhood, which is then updated by the columns entering and
+/// leaving the element, so the cost per pixel grows with the radius and not its square.
+fn rank_plane(
+    plane: &[u8],
+    width: u32,
+    height: u32,
+    element: StructuringElement,
+    edge: EdgeMode,
+    fill: u8,
+    rank: Rank,
+) -> Vec<u8> {
+    let mut output = vec![0u8; plane.len()];
+    if plane.is_empty() {
+        return output;
+    }
+
+    let spans = element.spans();
+    let count: u32 = spans.iter().map(|(_, half)| (half * 2 + 1) as u32).sum();
+
+    let read = |x: i64, y: i64| match edge.resolve(x, y, width, height) {
+        Some((px, py)) => plane[(py * width + px) as usize],
+        None => fill,
+    };
+
+    output
+        .par_chunks_mut(width as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let y = y as i64;
+            let mut histogram = [0u32; 256];
+
+            for (dy, half) in spans.iter() {
+                for dx in -half..=*half {
+                    histogram[read(dx, y + dy) as usize] += 1;
+                }
+            }
+
+            for (x, out) in row.iter_mut().enumerate() {
+                let x = x as i64;
+
+                if x > 0 {
+                    for (dy, half) in spans.iter() {
+                        histogram[read(x - 1 - half, y + dy) as usize] -= 1;
+                        histogram[read(x + half, y + dy) as usize] += 1;
+                    }
+                }
+
+                *out = match rank {
+                    Rank::Min => histogram.iter().position(|n| *n > 0).unwrap() as u8,
+                    Rank::Max => histogram.iter().rposition(|n| *n > 0).unwrap() as u8,
+                    Rank::Median => {
+                        let middle = count.div_ceil(2);
+                        let mut seen = 0;
+                        histogram
+                            .iter()
+                            .position(|n| {
+                                seen += n;
+                                seen >= middle
+                            })
+                            .unwrap() as u8
+                    }
+                };
+            }
+        });
+
+    output
+}
+
+/// Apply a MorphOp to a single channel plane.
+fn morph_plane(
+    plane: &[u8],
+    width: u32,
+    height: u32,
+    op: MorphOp,
+    element: StructuringElement,
+    edge: EdgeMode,
+    fill: u8,
+) -> Vec<u8> {
+    let rank = |plane: &[u8], rank| rank_plane(plane, width, height, element, edge, fill, rank);
+
+    match op {
+        MorphOp::Median => rank(plane, Rank::Median),
+        MorphOp::Erode => rank(plane, Rank::Min),
+        MorphOp::Dilate => rank(plane, Rank::Max),
+        MorphOp::Open => rank(&rank(plane, Rank::Min), Rank::Max),
+        MorphOp::Close => rank(&rank(plane, Rank::Max), Rank::Min),
+        MorphOp::Gradient => {
+            let dilated = rank(plane, Rank::Max);
+            let eroded = rank(plane, Rank::Min);
+            dilated.iter().zip(eroded).map(|(d, e)| d - e).collect()
+        }
+    }
+}
+
+/// Rank and morphological filtering. Every channel, alpha included, is filtered on its own,
+/// so median can produce colors that weren't in the neighbourhood.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `op` - The operation, see MorphOp.
+/// * `element` - The neighbourhood of each pixel.
+/// * `edge` - What is read past the edges of the image.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn morphology(
+    img: DynamicImage,
+    op: MorphOp,
+    element: StructuringElement,
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+
+    let fill = edge.fill();
+
+    let planes: Vec<Vec<u8>> = (0..4)
+        .map(|c| {
+            let plane: Vec<u8> = img.pixels().map(|pixel| pixel[c]).collect();
+            morph_plane(&plane, width, height, op, element, edge, fill[c])
+        })
+        .collect();
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let i = (y * width + x) as usize;
+        let out = Rgba([planes[0][i], planes[1][i], planes[2][i], planes[3][i]]);
+
+        mix_pixels(*img.get_pixel(x, y), out, mix)
+    })
+}
+
+/// Rank and morphological filtering of a greyscale mask, such as the output of filter_mask.
+/// * `mask` - The mask to filter.
+/// * `op` - The operation, see MorphOp.
+/// * `element` - The neighbourhood of each pixel.
+/// * `edge` - What is read past the edges of the mask. Transparent reads 0, Constant reads the
+///   red channel of its color.
+pub fn morphology_mask(
+    mask: &GrayImage,
+    op: MorphOp,
+    element: StructuringElement,
+    edge: EdgeMode,
+) -> GrayImage {
+    let (width, height) = mask.dimensions();
+
+    let plane = morph_plane(
+        mask.as_raw(),
+        width,
+        height,
+        op,
+        element,
+        edge,
+        edge.fill()[0],
+    );
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        Luma([plane[(y * width + x) as usize]])
+    })
+}
+
+/// Median filter, see morphology.
+pub fn median(
+    img: DynamicImage,
+    element: StructuringElement,
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    morphology(img, MorphOp::Median, element, edge, mix)
+}
+
+/// Minimum filter, see morphology.
+pub fn erode(
+    img: DynamicImage,
+    element: StructuringElement,
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    morphology(img, MorphOp::Erode, element, edge, mix)
+}
+
+/// Maximum filter, see morphology.
+pub fn dilate(
+    img: DynamicImage,
+    element: StructuringElement,
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    morphology(img, MorphOp::Dilate, element, edge, mix)
+}
+
+/// Erode then dilate, see morphology.
+pub fn opening(
+    img: DynamicImage,
+    element: StructuringElement,
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    morphology(img, MorphOp::Open, element, edge, mix)
+}
+
+/// Dilate then erode, see morphology.
+pub fn closing(
+    img: DynamicImage,
+    element: StructuringElement,
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    morphology(img, MorphOp::Close, element, edge, mix)
+}
+
+/// Dilate minus erode, see morphology.
+pub fn morphological_gradient(
+    img: DynamicImage,
+    element: StructuringElement,
+    edge: EdgeMode,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    morphology(img, MorphOp::Gradient, element, edge, mix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 9x9 mask with a 3x3 square in the middle and a single speck in the corner.
+    fn mask() -> GrayImage {
+        ImageBuffer::from_fn(9, 9, |x, y| {
+            let square = (3..6).contains(&x) && (3..6).contains(&y);
+            Luma([if square || (x, y) == (0, 8) { 255 } else { 0 }])
+        })
+    }
+
+    fn count(mask: &GrayImage) -> usize {
+        mask.pixels().filter(|pixel| pixel[0] == 255).count()
+    }
+
+    /// Brute force rank filter to check the sliding histogram against.
+    fn brute_force(plane: &GrayImage, element: StructuringElement, rank: Rank) -> GrayImage {
+        let spans = element.spans();
+        let (width, height) = plane.dimensions();
+
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let mut values: Vec<u8> = spans
+                .iter()
+                .flat_map(|(dy, half)| {
+                    (-half..=*half).map(move |dx| {
+                        let (px, py) = EdgeMode::Mirror
+                            .resolve(x as i64 + dx, y as i64 + dy, width, height)
+                            .unwrap();
+                        plane.get_pixel(px, py)[0]
+                    })
+                })
+                .collect();
+            values.sort();
+
+            Luma([match rank {
+                Rank::Min => values[0],
+                Rank::Max => values[values.len() - 1],
+                Rank::Median => values[(values.len() - 1) / 2],
+            }])
+        })
+    }
+
+    #[test]
+    fn test_spans() {
+        assert_eq!(StructuringElement::Square(1).spans().len(), 3);
+
+        let disc: Vec<i64> = StructuringElement::Circle(2)
+            .spans()
+            .iter()
+            .map(|(_, half)| *half)
+            .collect();
+        assert_eq!(disc, vec![1, 2, 2, 2, 1]);
+
+        assert_eq!(
+            "circle:3".parse::<StructuringElement>(),
+            Ok(StructuringElement::Circle(3))
+        );
+        assert!("star:3".parse::<StructuringElement>().is_err());
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let noise: GrayImage = ImageBuffer::from_fn(13, 11, |x, y| {
+            Luma([((x * 73 + y * 151 + x * y * 7) % 256) as u8])
+        });
+
+        for element in [StructuringElement::Square(2), StructuringElement::Circle(3)] {
+            for (op, rank) in [
+                (MorphOp::Median, Rank::Median),
+                (MorphOp::Erode, Rank::Min),
+                (MorphOp::Dilate, Rank::Max),
+            ] {
+                assert_eq!(
+                    morphology_mask(&noise, op, element, EdgeMode::Mirror),
+                    brute_force(&noise, element, rank)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_erode_dilate() {
+        let element = StructuringElement::Square(1);
+
+        let eroded = morphology_mask(&mask(), MorphOp::Erode, element, EdgeMode::Clamp);
+        assert_eq!(count(&eroded), 1);
+        assert_eq!(eroded.get_pixel(4, 4)[0], 255);
+
+        let dilated = morphology_mask(&mask(), MorphOp::Dilate, element, EdgeMode::Clamp);
+        assert_eq!(count(&dilated), 25 + 4);
+    }
+
+    #[test]
+    fn test_open_close_gradient() {
+        let element = StructuringElement::Square(1);
+
+        // Opening removes the speck but keeps the square
+        let opened = morphology_mask(&mask(), MorphOp::Open, element, EdgeMode::Clamp);
+        assert_eq!(count(&opened), 9);
+        assert_eq!(opened.get_pixel(0, 8)[0], 0);
+
+        // Closing fills a one pixel hole
+        let mut holed = mask();
+        holed.put_pixel(4, 4, Luma([0]));
+        let closed = morphology_mask(&holed, MorphOp::Close, element, EdgeMode::Clamp);
+        assert_eq!(closed.get_pixel(4, 4)[0], 255);
+
+        // The gradient of the square is a ring around its centre
+        let gradient = morphology_mask(&opened, MorphOp::Gradient, element, EdgeMode::Clamp);
+        assert_eq!(gradient.get_pixel(4, 4)[0], 0);
+        assert_eq!(gradient.get_pixel(3, 3)[0], 255);
+        assert_eq!(count(&gradient), 25 - 1);
+    }
+
+    #[test]
+    fn test_median_rgba() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(5, 5, |x, y| {
+            if (x, y) == (2, 2) {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([10, 20, 30, 255])
+            }
+        }));
+
+        let out = median(img, StructuringElement::Circle(1), EdgeMode::Clamp, None);
+        assert!(out.pixels().all(|pixel| *pixel == Rgba([10, 20, 30, 255])));
+    }
+
+    #[test]
+    fn test_transparent_edge_erodes_alpha() {
+        let img =
+            DynamicImage::ImageRgba8(ImageBuffer::from_pixel(3, 3, Rgba([100, 100, 100, 255])));
+
+        let out = erode(
+            img,
+            StructuringElement::Square(1),
+            EdgeMode::Transparent,
+            None,
+        );
+        assert_eq!(*out.get_pixel(1, 1), Rgba([100, 100, 100, 255]));
+        assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+    }
+}
@@ -0,0 +1,530 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::utils::Rng;
+use image::imageops::{resize, FilterType};
+use image::{DynamicImage, GrayImage, ImageBuffer, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// How the color of a mosaic cell is picked from the pixels it covers.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CellSampling {
+    /// Mean of every pixel in the cell.
+    Average,
+    /// The pixel of the cell closest to its centre.
+    Centre,
+    /// Per channel median of the pixels in the cell, ignores outliers.
+    Median,
+}
+
+/// Clap FromStr
+impl FromStr for CellSampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "average" | "mean" => Ok(CellSampling::Average),
+            "centre" | "center" => Ok(CellSampling::Centre),
+            "median" => Ok(CellSampling::Median),
+
+            _ => Err(format!("Invalid CellSampling name: {}", s)),
+        }
+    }
+}
+
+/// Fill every cell with one color and blend it over the image through the mask.
+///
+/// `cell_of` gives the cell index of each pixel, `centres` the centre of each cell in pixels.
+fn mosaic(
+    img: RgbaImage,
+    cell_of: Vec<usize>,
+    centres: Vec<(f64, f64)>,
+    sampling: CellSampling,
+    mask: Option<&GrayImage>,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+
+    // Stretch a mask of another size over the image
+    let stretched = match mask {
+        Some(mask) if mask.dimensions() != (width, height) => {
+            Some(resize(mask, width, height, FilterType::Triangle))
+        }
+        _ => None,
+    };
+    let mask = stretched.as_ref().or(mask);
+
+    let pixels: Vec<[u8; 4]> = img.pixels().map(|pixel| pixel.0).collect();
+    let cells = centres.len();
+
+    let colors: Vec<[u8; 4]> = match sampling {
+        CellSampling::Average => {
+            let mut sums = vec![([0u64; 4], 0u64); cells];
+            for (pixel, cell) in pixels.iter().zip(cell_of.iter()) {
+                for (sum, channel) in sums[*cell].0.iter_mut().zip(pixel) {
+                    *sum += *channel as u64;
+                }
+                sums[*cell].1 += 1;
+            }
+
+            sums.iter()
+                .map(|(sum, count)| {
+                    let count = (*count).max(1);
+                    sum.map(|channel| ((channel + count / 2) / count) as u8)
+                })
+                .collect()
+        }
+        CellSampling::Centre => {
+            // The pixel of each cell closest to its centre, which may lie outside of the image
+            let mut closest = vec![(f64::INFINITY, [0u8; 4]); cells];
+            for (i, (pixel, cell)) in pixels.iter().zip(cell_of.iter()).enumerate() {
+                let (x, y) = ((i % width as usize) as f64, (i / width as usize) as f64);
+                let (cx, cy) = centres[*cell];
+                let distance = (x - cx).powi(2) + (y - cy).powi(2);
+
+                // Ties go to the later pixel, like rounding a centre between pixels up
+                if distance <= closest[*cell].0 {
+                    closest[*cell] = (distance, *pixel);
+                }
+            }
+
+            closest.into_iter().map(|(_, pixel)| pixel).collect()
+        }
+        CellSampling::Median => {
+            // Counting sort of the pixels by cell, so each cell's pixels are contiguous
+            let mut offsets = vec![0usize; cells + 1];
+            for cell in cell_of.iter() {
+                offsets[cell + 1] += 1;
+            }
+            for i in 0..cells {
+                offsets[i + 1] += offsets[i];
+            }
+
+            let mut next = offsets.clone();
+            let mut sorted = vec![[0u8; 4]; pixels.len()];
+            for (pixel, cell) in pixels.iter().zip(cell_of.iter()) {
+                sorted[next[*cell]] = *pixel;
+                next[*cell] += 1;
+            }
+
+            (0..cells)
+                .into_par_iter()
+                .map(|cell| {
+                    let members = &sorted[offsets[cell]..offsets[cell + 1]];
+                    if members.is_empty() {
+                        return [0; 4];
+                    }
+
+                    [0, 1, 2, 3].map(|c| {
+                        let mut values: Vec<u8> = members.iter().map(|pixel| pixel[c]).collect();
+                        let middle = (values.len() - 1) / 2;
+                        *values.select_nth_unstable(middle).1
+                    })
+                })
+                .collect()
+        }
+    };
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let i = (y * width + x) as usize;
+        let (original, cell) = (pixels[i], colors[cell_of[i]]);
+
+        *pixel = match mask.map(|mask| mask.get_pixel(x, y)[0]) {
+            None | Some(255) => Rgba(cell),
+            Some(0) => Rgba(original),
+            Some(amount) => Rgba([0, 1, 2, 3].map(|c| {
+                let t = amount as f64 / 255.0;
+                (original[c] as f64 + (cell[c] as f64 - original[c] as f64) * t).round() as u8
+            })),
+        };
+    });
+
+    output
+}
+
+/// Number the distinct cell keys of every pixel and find the centre of each cell.
+fn cells_from_keys(
+    width: u32,
+    height: u32,
+    key_of: impl Fn(f64, f64) -> (i64, i64) + Sync,
+    centre_of: impl Fn((i64, i64)) -> (f64, f64),
+) -> (Vec<usize>, Vec<(f64, f64)>) {
+    // Pixels are sampled at their centres
+    let keys: Vec<(i64, i64)> = (0..width as usize * height as usize)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = (i % width as usize, i / width as usize);
+            key_of(x as f64 + 0.5, y as f64 + 0.5)
+        })
+        .collect();
+
+    let mut ids: HashMap<(i64, i64), usize> = HashMap::new();
+    let mut centres = vec![];
+
+    let cell_of = keys
+        .iter()
+        .map(|key| {
+            *ids.entry(*key).or_insert_with(|| {
+                let (x, y) = centre_of(*key);
+                centres.push((x - 0.5, y - 0.5));
+                centres.len() - 1
+            })
+        })
+        .collect();
+
+    (cell_of, centres)
+}
+
+/// Square pixelation.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `cell_size` - Side of the square cells in pixels.
+/// * `sampling` - How each cell's color is picked.
+/// * `mask` - Optional mask of where the effect applies, 255 fully and 0 not at all. See
+///   filter_mask. A mask of a different size is stretched over the image.
+pub fn pixelate(
+    img: DynamicImage,
+    cell_size: u32,
+    sampling: CellSampling,
+    mask: Option<&GrayImage>,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+    let size = cell_size.max(1) as f64;
+
+    let (cell_of, centres) = cells_from_keys(
+        width,
+        height,
+        |x, y| ((x / size).floor() as i64, (y / size).floor() as i64),
+        |(cx, cy)| {
+            // The centre of a cell clipped by the edge of the image
+            let centre = |c: i64, limit: u32| {
+                let start = c as f64 * size;
+                (start + (start + size).min(limit as f64)) / 2.0
+            };
+            (centre(cx, width), centre(cy, height))
+        },
+    );
+
+    mosaic(img, cell_of, centres, sampling, mask)
+}
+
+/// Hexagonal mosaic of pointy-top hexagons.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `size` - Distance from the centre of a hexagon to its corners, in pixels.
+/// * `sampling` - How each cell's color is picked.
+/// * `mask` - Optional mask of where the effect applies, 255 fully and 0 not at all. A mask of
+///   a different size is stretched over the image.
+pub fn hexagonal_mosaic(
+    img: DynamicImage,
+    size: f64,
+    sampling: CellSampling,
+    mask: Option<&GrayImage>,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+    let size = size.max(0.5);
+    let sqrt3 = 3f64.sqrt();
+
+    let (cell_of, centres) = cells_from_keys(
+        width,
+        height,
+        |x, y| {
+            // Axial coordinates, rounded through cube coordinates
+            let q = (sqrt3 / 3.0 * x - y / 3.0) / size;
+            let r = (2.0 / 3.0 * y) / size;
+            let s = -q - r;
+
+            let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+            let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+
+            if dq > dr && dq > ds {
+                rq = -rr - rs;
+            } else if dr > ds {
+                rr = -rq - rs;
+            }
+
+            (rq as i64, rr as i64)
+        },
+        |(q, r)| {
+            let (q, r) = (q as f64, r as f64);
+            (size * (sqrt3 * q + sqrt3 / 2.0 * r), size * 1.5 * r)
+        },
+    );
+
+    mosaic(img, cell_of, centres, sampling, mask)
+}
+
+/// Triangular mosaic of equilateral triangles, alternating up and down in each row.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `size` - Side of the triangles in pixels.
+/// * `sampling` - How each cell's color is picked.
+/// * `mask` - Optional mask of where the effect applies, 255 fully and 0 not at all. A mask of
+///   a different size is stretched over the image.
+pub fn triangular_mosaic(
+    img: DynamicImage,
+    size: f64,
+    sampling: CellSampling,
+    mask: Option<&GrayImage>,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+    let size = size.max(1.0);
+    let row_height = size * 3f64.sqrt() / 2.0;
+
+    let (cell_of, centres) = cells_from_keys(
+        width,
+        height,
+        |x, y| {
+            // Skewed coordinates where each rhombus splits into two triangles
+            let v = y / row_height;
+            let u = x / size - v / 2.0;
+            let (cu, cv) = (u.floor(), v.floor());
+            let upper = (u - cu) + (v - cv) >= 1.0;
+
+            (cu as i64 * 2 + upper as i64, cv as i64)
+        },
+        |(key, cv)| {
+            let (cu, upper) = (key.div_euclid(2) as f64, key.rem_euclid(2) == 1);
+            let third = if upper { 2.0 / 3.0 } else { 1.0 / 3.0 };
+
+            let (u, v) = (cu + third, cv as f64 + third);
+            ((u + v / 2.0) * size, v * row_height)
+        },
+    );
+
+    mosaic(img, cell_of, centres, sampling, mask)
+}
+
+/// Voronoi mosaic. Scatters random seed points and gives every pixel the cell of its closest
+/// seed. The same seed always gives the same cells.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `cells` - Number of seed points.
+/// * `seed` - Seed of the random point positions.
+/// * `sampling` - How each cell's color is picked, Centre reads the pixel under the seed.
+/// * `mask` - Optional mask of where the effect applies, 255 fully and 0 not at all. A mask of
+///   a different size is stretched over the image.
+pub fn voronoi_mosaic(
+    img: DynamicImage,
+    cells: usize,
+    seed: u64,
+    sampling: CellSampling,
+    mask: Option<&GrayImage>,
+) -> RgbaImage {
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+
+    if width == 0 || height == 0 {
+        return img;
+    }
+
+    let mut rng = Rng::new(seed);
+    let points: Vec<(f64, f64)> = (0..cells.max(1))
+        .map(|_| {
+            (
+                rng.next_f64() * width as f64,
+                rng.next_f64() * height as f64,
+            )
+        })
+        .collect();
+
+    let cell_of = nearest_points(&points, width, height);
+    let centres = points.iter().map(|(x, y)| (x - 0.5, y - 0.5)).collect();
+
+    mosaic(img, cell_of, centres, sampling, mask)
+}
+
+/// Index of the closest point to every pixel centre, using a bucket grid so only nearby points
+/// are checked.
+fn nearest_points(points: &[(f64, f64)], width: u32, height: u32) -> Vec<usize> {
+    let bucket = ((width as f64 * height as f64) / points.len() as f64)
+        .sqrt()
+        .max(1.0);
+    let (columns, rows) = (
+        (width as f64 / bucket).ceil() as i64,
+        (height as f64 / bucket).ceil() as i64,
+    );
+
+    let mut buckets: Vec<Vec<usize>> = vec![vec![]; (columns * rows) as usize];
+    for (i, (x, y)) in points.iter().enumerate() {
+        let bx = ((x / bucket) as i64).min(columns - 1);
+        let by = ((y / bucket) as i64).min(rows - 1);
+        buckets[(by * columns + bx) as usize].push(i);
+    }
+
+    (0..width as usize * height as usize)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = (
+                (i % width as usize) as f64 + 0.5,
+                (i / width as usize) as f64 + 0.5,
+            );
+            let (bx, by) = ((x / bucket) as i64, (y / bucket) as i64);
+
+            let mut best = (usize::MAX, f64::INFINITY);
+
+            // Search rings of buckets until no closer point can be in the next ring
+            for ring in 0.. {
+                for gy in by - ring..=by + ring {
+                    for gx in bx - ring..=bx + ring {
+                        let on_ring = (gy - by).abs() == ring || (gx - bx).abs() == ring;
+                        if !on_ring || gx < 0 || gy < 0 || gx >= columns || gy >= rows {
+                            continue;
+                        }
+
+                        for point in buckets[(gy * columns + gx) as usize].iter() {
+                            let (px, py) = points[*point];
+                            let distance = (px - x).powi(2) + (py - y).powi(2);
+
+                            if distance < best.1 || (distance == best.1 && *point < best.0) {
+                                best = (*point, distance);
+                            }
+                        }
+                    }
+                }
+
+                let reach = ring as f64 * bucket;
+                if best.0 != usize::MAX && best.1 <= reach * reach {
+                    break;
+                }
+                if ring > columns.max(rows) {
+                    break;
+                }
+            }
+
+            best.0
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_images::gradient;
+    use image::Luma;
+
+    fn distinct(img: &RgbaImage) -> usize {
+        let mut colors: Vec<[u8; 4]> = img.pixels().map(|pixel| pixel.0).collect();
+        colors.sort();
+        colors.dedup();
+        colors.len()
+    }
+
+    #[test]
+    fn test_pixelate_sampling() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 2, |x, y| {
+            let v = [[0, 10, 100, 110], [20, 90, 120, 250]][y as usize][x as usize];
+            Rgba([v, v, v, 255])
+        }));
+
+        let out = pixelate(img.clone(), 2, CellSampling::Average, None);
+        assert_eq!(out.get_pixel(0, 0)[0], 30);
+        assert_eq!(out.get_pixel(3, 1)[0], 145);
+
+        let out = pixelate(img.clone(), 2, CellSampling::Median, None);
+        assert_eq!(out.get_pixel(1, 1)[0], 10);
+
+        let out = pixelate(img, 2, CellSampling::Centre, None);
+        assert_eq!(out.get_pixel(0, 0)[0], 90);
+    }
+
+    #[test]
+    fn test_pixelate_partial_cells() {
+        let out = pixelate(gradient(10, 10), 4, CellSampling::Average, None);
+        assert_eq!(distinct(&out), 9);
+        assert_eq!(out.get_pixel(8, 8), out.get_pixel(9, 9));
+    }
+
+    #[test]
+    fn test_mask() {
+        let img = gradient(8, 8);
+        let mask: GrayImage =
+            ImageBuffer::from_fn(8, 8, |x, _| Luma([if x < 4 { 255 } else { 0 }]));
+
+        let out = pixelate(img.clone(), 4, CellSampling::Average, Some(&mask));
+
+        assert_eq!(out.get_pixel(0, 0), out.get_pixel(3, 3));
+        for x in 4..8 {
+            assert_eq!(out.get_pixel(x, 5), img.to_rgba8().get_pixel(x, 5));
+        }
+    }
+
+    #[test]
+    fn test_mask_is_stretched() {
+        let img = gradient(8, 8);
+        let mask: GrayImage =
+            ImageBuffer::from_fn(2, 2, |x, _| Luma([if x == 0 { 255 } else { 0 }]));
+
+        let out = pixelate(img.clone(), 4, CellSampling::Average, Some(&mask));
+
+        assert_eq!(out.get_pixel(0, 0), out.get_pixel(1, 3));
+        assert_eq!(out.get_pixel(7, 5), img.to_rgba8().get_pixel(7, 5));
+    }
+
+    #[test]
+    fn test_centre_stays_in_cell() {
+        // Every input color is unique, so each cell's color must come from one of its own pixels
+        let img = gradient(30, 30);
+        let input = img.to_rgba8();
+
+        for out in [
+            hexagonal_mosaic(img.clone(), 6.0, CellSampling::Centre, None),
+            triangular_mosaic(img.clone(), 9.0, CellSampling::Centre, None),
+        ] {
+            for color in out.pixels() {
+                assert!(input
+                    .enumerate_pixels()
+                    .any(|(x, y, pixel)| pixel == color && out.get_pixel(x, y) == color));
+            }
+        }
+    }
+
+    #[test]
+    fn test_hexagonal() {
+        let out = hexagonal_mosaic(gradient(32, 32), 5.0, CellSampling::Average, None);
+
+        // Neighbours across a vertical line through a hexagon centre share its color
+        assert_eq!(out.get_pixel(0, 0), out.get_pixel(1, 0));
+        assert!(distinct(&out) > 10 && distinct(&out) < 60);
+    }
+
+    #[test]
+    fn test_triangular() {
+        let out = triangular_mosaic(gradient(32, 32), 8.0, CellSampling::Centre, None);
+
+        // Each row of triangles is split between up and down triangles
+        assert_ne!(out.get_pixel(4, 1), out.get_pixel(0, 5));
+        assert!(distinct(&out) > 10);
+    }
+
+    #[test]
+    fn test_voronoi() {
+        let img = gradient(40, 30);
+
+        let a = voronoi_mosaic(img.clone(), 12, 42, CellSampling::Average, None);
+        let b = voronoi_mosaic(img.clone(), 12, 42, CellSampling::Average, None);
+        assert_eq!(a, b);
+        assert!(distinct(&a) <= 12);
+
+        let c = voronoi_mosaic(img, 12, 7, CellSampling::Average, None);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_nearest_points_matches_brute_force() {
+        let mut rng = Rng::new(3);
+        let points: Vec<(f64, f64)> = (0..25)
+            .map(|_| (rng.next_f64() * 37.0, rng.next_f64() * 23.0))
+            .collect();
+
+        let nearest = nearest_points(&points, 37, 23);
+
+        for (i, cell) in nearest.iter().enumerate() {
+            let (x, y) = ((i % 37) as f64 + 0.5, (i / 37) as f64 + 0.5);
+            let distance = |p: &(f64, f64)| (p.0 - x).powi(2) + (p.1 - y).powi(2);
+
+            let best = points.iter().map(distance).fold(f64::INFINITY, f64::min);
+            assert_eq!(distance(&points[*cell]), best);
+        }
+    }
+}
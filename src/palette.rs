@@ -0,0 +1,814 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::utils::{hex_to_rgb, lab_to_rgb, mix_pixels, rgb_to_lab, Mix};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// A list of colors to reduce or map an image to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: Vec<Rgb<u8>>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Rgb<u8>>) -> Self {
+        Palette { colors }
+    }
+
+    /// Parse a list of hex colors separated by whitespace, commas or newlines, e.g.
+    /// "#ff0000 00ff00, 0000ff". Lines starting with ";" or "//" are comments. Eight digit
+    /// colors without a "#" are read as AARRGGBB, as in paint.net palettes, and the alpha is
+    /// dropped.
+    pub fn from_hex_list(text: &str) -> Result<Self, String> {
+        let mut colors = vec![];
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.starts_with(';') || line.starts_with("//") {
+                continue;
+            }
+
+            for hex in line.split(|c: char| c.is_whitespace() || c == ',') {
+                if hex.is_empty() {
+                    continue;
+                }
+
+                let hex = if hex.len() == 8 && hex.is_ascii() && !hex.starts_with('#') {
+                    &hex[2..]
+                } else {
+                    hex
+                };
+
+                let (r, g, b) = hex_to_rgb(hex).ok_or(format!("Invalid hex color: {}", hex))?;
+                colors.push(Rgb([r, g, b]));
+            }
+        }
+
+        if colors.is_empty() {
+            return Err("Palette has no colors".to_string());
+        }
+
+        Ok(Palette { colors })
+    }
+
+    /// Parse a GIMP palette (.gpl) file.
+    pub fn from_gpl(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+
+        match lines.next() {
+            Some(header) if header.trim() == "GIMP Palette" => {}
+            _ => return Err("Missing \"GIMP Palette\" header".to_string()),
+        }
+
+        let mut colors = vec![];
+
+        for line in lines {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("Name:")
+                || line.starts_with("Columns:")
+            {
+                continue;
+            }
+
+            let channels: Vec<u8> = line
+                .split_whitespace()
+                .take(3)
+                .map(|channel| channel.parse::<u8>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("Invalid GIMP palette line: {}", line))?;
+
+            match channels.as_slice() {
+                [r, g, b] => colors.push(Rgb([*r, *g, *b])),
+                _ => return Err(format!("Invalid GIMP palette line: {}", line)),
+            }
+        }
+
+        if colors.is_empty() {
+            return Err("Palette has no colors".to_string());
+        }
+
+        Ok(Palette { colors })
+    }
+}
+
+/// Clap FromStr, a hex color list.
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Palette::from_hex_list(s)
+    }
+}
+
+/// A node of the palette k-d tree.
+#[derive(Clone, Debug)]
+struct KdNode {
+    /// Index of the color in the palette.
+    index: usize,
+    axis: usize,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+/// Nearest color lookup into a Palette, accelerated by a k-d tree over RGB.
+#[derive(Clone, Debug)]
+pub struct PaletteMapper {
+    palette: Palette,
+    nodes: Vec<KdNode>,
+    root: Option<usize>,
+}
+
+impl PaletteMapper {
+    pub fn new(palette: &Palette) -> Self {
+        let mut mapper = PaletteMapper {
+            palette: palette.clone(),
+            nodes: Vec::with_capacity(palette.colors.len()),
+            root: None,
+        };
+
+        let mut indices: Vec<usize> = (0..palette.colors.len()).collect();
+        mapper.root = mapper.build(&mut indices, 0);
+
+        mapper
+    }
+
+    fn build(&mut self, indices: &mut [usize], depth: usize) -> Option<usize> {
+        if indices.is_empty() {
+            return None;
+        }
+
+        let axis = depth % 3;
+        indices.sort_by_key(|index| self.palette.colors[*index].0[axis]);
+
+        let median = indices.len() / 2;
+        let (left, rest) = indices.split_at_mut(median);
+        let (index, right) = rest.split_first_mut().unwrap();
+
+        let node = self.nodes.len();
+        self.nodes.push(KdNode {
+            index: *index,
+            axis,
+            left: None,
+            right: None,
+        });
+
+        self.nodes[node].left = self.build(left, depth + 1);
+        self.nodes[node].right = self.build(right, depth + 1);
+
+        Some(node)
+    }
+
+    fn distance(&self, index: usize, color: [u8; 3]) -> i32 {
+        let p = self.palette.colors[index].0;
+        (0..3).map(|i| (p[i] as i32 - color[i] as i32).pow(2)).sum()
+    }
+
+    fn search(&self, node: Option<usize>, color: [u8; 3], best: &mut (usize, i32)) {
+        let Some(node) = node else {
+            return;
+        };
+        let node = &self.nodes[node];
+
+        let distance = self.distance(node.index, color);
+        if distance < best.1 || (distance == best.1 && node.index < best.0) {
+            *best = (node.index, distance);
+        }
+
+        let split = self.palette.colors[node.index].0[node.axis] as i32;
+        let delta = color[node.axis] as i32 - split;
+
+        let (near, far) = if delta < 0 {
+            (node.left, node.right)
+        } else {
+            (node.right, node.left)
+        };
+
+        self.search(near, color, best);
+        if delta * delta <= best.1 {
+            self.search(far, color, best);
+        }
+    }
+
+    /// Index into the palette of the color closest to `color` by euclidean RGB distance. Ties go
+    /// to the color listed first.
+    ///
+    /// Panics if the palette is empty.
+    pub fn nearest_index(&self, color: Rgb<u8>) -> usize {
+        assert!(self.root.is_some(), "Palette has no colors");
+
+        let mut best = (usize::MAX, i32::MAX);
+        self.search(self.root, color.0, &mut best);
+
+        best.0
+    }
+
+    /// The palette color closest to `color`.
+    pub fn nearest(&self, color: Rgb<u8>) -> Rgb<u8> {
+        self.palette.colors[self.nearest_index(color)]
+    }
+}
+
+/// Map every pixel to the nearest color of a palette. Alpha is passed through.
+///
+/// Panics if the palette is empty.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `palette` - The colors to map to.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn map_to_palette(img: DynamicImage, palette: &Palette, mix: Option<Mix>) -> RgbaImage {
+    let mapper = PaletteMapper::new(palette);
+
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    // Images tend to repeat colors, so every worker caches its lookups
+    output.par_enumerate_pixels_mut().for_each_init(
+        HashMap::<[u8; 3], Rgb<u8>>::new,
+        |cache, (x, y, pixel)| {
+            let in_pixel = img.get_pixel(x, y);
+            let key = [in_pixel[0], in_pixel[1], in_pixel[2]];
+
+            let color = *cache.entry(key).or_insert_with(|| mapper.nearest(Rgb(key)));
+
+            let out = Rgba([color.0[0], color.0[1], color.0[2], in_pixel[3]]);
+
+            *pixel = mix_pixels(in_pixel, out, mix);
+        },
+    );
+
+    output
+}
+
+/// Color quantization algorithms used to build a palette from an image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QuantizeMethod {
+    /// Recursively split the color box along its widest channel at the median.
+    MedianCut,
+    /// K-means clustering in CIE Lab, seeded with the median cut palette.
+    KMeans,
+    /// Merge the least used leaves of an octree of the colors.
+    Octree,
+}
+
+/// Clap FromStr
+impl FromStr for QuantizeMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['-', '_'], "").as_str() {
+            "mediancut" => Ok(QuantizeMethod::MedianCut),
+            "kmeans" => Ok(QuantizeMethod::KMeans),
+            "octree" => Ok(QuantizeMethod::Octree),
+
+            _ => Err(format!("Invalid QuantizeMethod name: {}", s)),
+        }
+    }
+}
+
+/// A bucket of similar colors from the histogram: their mean color and how many pixels they
+/// cover.
+#[derive(Clone, Copy, Debug)]
+struct HistogramEntry {
+    color: [f64; 3],
+    weight: f64,
+}
+
+/// Histogram of the image with 5 bits per channel, keeping the mean of the exact colors in each
+/// bucket. Fully transparent pixels are ignored.
+fn histogram(img: &DynamicImage) -> Vec<HistogramEntry> {
+    let mut buckets: HashMap<u16, ([u64; 3], u64)> = HashMap::new();
+
+    for (_, _, pixel) in img.pixels() {
+        if pixel[3] == 0 {
+            continue;
+        }
+
+        let key = (pixel[0] as u16 >> 3) << 10 | (pixel[1] as u16 >> 3) << 5 | pixel[2] as u16 >> 3;
+
+        let bucket = buckets.entry(key).or_insert(([0; 3], 0));
+        for i in 0..3 {
+            bucket.0[i] += pixel[i] as u64;
+        }
+        bucket.1 += 1;
+    }
+
+    let mut entries: Vec<(u16, HistogramEntry)> = buckets
+        .into_iter()
+        .map(|(key, (sum, count))| {
+            let color = sum.map(|channel| channel as f64 / count as f64);
+            (
+                key,
+                HistogramEntry {
+                    color,
+                    weight: count as f64,
+                },
+            )
+        })
+        .collect();
+
+    // HashMap order is random, keep the quantizers deterministic
+    entries.sort_by_key(|(key, _)| *key);
+
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+fn to_rgb(color: [f64; 3]) -> Rgb<u8> {
+    Rgb(color.map(|channel| channel.round().clamp(0.0, 255.0) as u8))
+}
+
+fn weighted_mean(entries: &[HistogramEntry]) -> [f64; 3] {
+    let total: f64 = entries.iter().map(|entry| entry.weight).sum();
+
+    let mut mean = [0.0; 3];
+    for entry in entries {
+        for (channel, value) in mean.iter_mut().enumerate() {
+            *value += entry.color[channel] * entry.weight / total;
+        }
+    }
+
+    mean
+}
+
+fn median_cut(entries: Vec<HistogramEntry>, colors: usize) -> Vec<[f64; 3]> {
+    let range = |entries: &[HistogramEntry]| {
+        (0..3)
+            .map(|channel| {
+                let values = entries.iter().map(|entry| entry.color[channel]);
+                let min = values.clone().fold(f64::INFINITY, f64::min);
+                let max = values.fold(f64::NEG_INFINITY, f64::max);
+                (max - min, channel)
+            })
+            .fold(
+                (-1.0, 0),
+                |best, current| {
+                    if current.0 > best.0 {
+                        current
+                    } else {
+                        best
+                    }
+                },
+            )
+    };
+
+    let mut boxes: Vec<Vec<HistogramEntry>> = vec![entries];
+
+    while boxes.len() < colors {
+        // Split the box with the widest channel range
+        let Some((index, (_, channel))) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, entries)| entries.len() > 1)
+            .map(|(index, entries)| (index, range(entries)))
+            .fold(
+                None,
+                |best: Option<(usize, (f64, usize))>, current| match best {
+                    Some(best) if best.1 .0 >= current.1 .0 => Some(best),
+                    _ => Some(current),
+                },
+            )
+        else {
+            break;
+        };
+
+        let mut entries = boxes.swap_remove(index);
+        entries.sort_by(|a, b| a.color[channel].total_cmp(&b.color[channel]));
+
+        let total: f64 = entries.iter().map(|entry| entry.weight).sum();
+        let mut acc = 0.0;
+        let mut split = entries.len() - 1;
+        for (i, entry) in entries.iter().enumerate() {
+            acc += entry.weight;
+            if acc >= total / 2.0 {
+                split = i + 1;
+                break;
+            }
+        }
+        let split = split.clamp(1, entries.len() - 1);
+
+        let upper = entries.split_off(split);
+        boxes.push(entries);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|entries| weighted_mean(entries)).collect()
+}
+
+fn k_means(entries: Vec<HistogramEntry>, colors: usize) -> Vec<[f64; 3]> {
+    const MAX_ITERATIONS: usize = 24;
+
+    let lab = |color: [f64; 3]| {
+        let (l, a, b) = rgb_to_lab(to_rgb(color));
+        [l, a, b]
+    };
+
+    let points: Vec<[f64; 3]> = entries.par_iter().map(|entry| lab(entry.color)).collect();
+    let mut centers: Vec<[f64; 3]> = median_cut(entries.clone(), colors)
+        .into_iter()
+        .map(lab)
+        .collect();
+
+    let nearest = |point: &[f64; 3], centers: &[[f64; 3]]| {
+        let distance = |center: &[f64; 3]| (0..3).map(|i| (point[i] - center[i]).powi(2)).sum();
+        (0..centers.len())
+            .min_by(|a, b| {
+                let (da, db): (f64, f64) = (distance(&centers[*a]), distance(&centers[*b]));
+                da.total_cmp(&db)
+            })
+            .unwrap()
+    };
+
+    let mut assignment: Vec<usize> = vec![usize::MAX; points.len()];
+
+    for _ in 0..MAX_ITERATIONS {
+        let next: Vec<usize> = points
+            .par_iter()
+            .map(|point| nearest(point, &centers))
+            .collect();
+
+        if next == assignment {
+            break;
+        }
+        assignment = next;
+
+        let mut sums = vec![([0.0f64; 3], 0.0f64); centers.len()];
+        for ((point, entry), cluster) in points.iter().zip(entries.iter()).zip(assignment.iter()) {
+            for (sum, value) in sums[*cluster].0.iter_mut().zip(point.iter()) {
+                *sum += value * entry.weight;
+            }
+            sums[*cluster].1 += entry.weight;
+        }
+
+        // Empty clusters keep their previous center
+        for (center, (sum, weight)) in centers.iter_mut().zip(sums.iter()) {
+            if *weight > 0.0 {
+                *center = sum.map(|value| value / weight);
+            }
+        }
+    }
+
+    // Drop clusters that ended up empty
+    let used: Vec<bool> = (0..centers.len())
+        .map(|cluster| assignment.contains(&cluster))
+        .collect();
+
+    centers
+        .into_iter()
+        .zip(used)
+        .filter(|(_, used)| *used)
+        .map(|(center, _)| {
+            let rgb = lab_to_rgb((center[0], center[1], center[2]));
+            rgb.0.map(|channel| channel as f64)
+        })
+        .collect()
+}
+
+/// A node of the octree quantizer.
+#[derive(Clone, Debug, Default)]
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    sum: [f64; 3],
+    weight: f64,
+    leaf: bool,
+}
+
+fn octree(entries: Vec<HistogramEntry>, colors: usize) -> Vec<[f64; 3]> {
+    const DEPTH: usize = 8;
+
+    let mut nodes: Vec<OctreeNode> = vec![OctreeNode::default()];
+    // Internal nodes per level, candidates for being merged into leaves
+    let mut levels: Vec<Vec<usize>> = vec![vec![]; DEPTH];
+    let mut leaves = 0;
+
+    for entry in entries.iter() {
+        let rgb = to_rgb(entry.color).0;
+        let mut node = 0;
+
+        for level in 0..DEPTH {
+            if nodes[node].leaf {
+                break;
+            }
+
+            let bit = 7 - level;
+            let child =
+                ((rgb[0] >> bit & 1) << 2 | (rgb[1] >> bit & 1) << 1 | rgb[2] >> bit & 1) as usize;
+
+            node = match nodes[node].children[child] {
+                Some(existing) => existing,
+                None => {
+                    let created = nodes.len();
+                    nodes.push(OctreeNode {
+                        leaf: level == DEPTH - 1,
+                        ..OctreeNode::default()
+                    });
+                    nodes[node].children[child] = Some(created);
+
+                    if level == DEPTH - 1 {
+                        leaves += 1;
+                    } else {
+                        levels[level + 1].push(created);
+                    }
+
+                    created
+                }
+            };
+        }
+
+        for i in 0..3 {
+            nodes[node].sum[i] += entry.color[i] * entry.weight;
+        }
+        nodes[node].weight += entry.weight;
+    }
+
+    // Merge the deepest, least used nodes until the palette is small enough
+    for level in (1..DEPTH).rev() {
+        let weight_of = |nodes: &Vec<OctreeNode>, node: usize| -> f64 {
+            let mut stack = vec![node];
+            let mut total = 0.0;
+            while let Some(node) = stack.pop() {
+                total += nodes[node].weight;
+                stack.extend(nodes[node].children.iter().flatten());
+            }
+            total
+        };
+
+        let mut candidates = levels[level].clone();
+        candidates.sort_by(|a, b| {
+            weight_of(&nodes, *b)
+                .total_cmp(&weight_of(&nodes, *a))
+                .then(b.cmp(a))
+        });
+
+        while leaves > colors {
+            let Some(node) = candidates.pop() else {
+                break;
+            };
+
+            let children: Vec<usize> = nodes[node].children.iter().flatten().copied().collect();
+            for child in children.iter() {
+                let (sum, weight) = (nodes[*child].sum, nodes[*child].weight);
+                for (i, value) in sum.iter().enumerate() {
+                    nodes[node].sum[i] += value;
+                }
+                nodes[node].weight += weight;
+            }
+
+            nodes[node].children = [None; 8];
+            nodes[node].leaf = true;
+            leaves = leaves + 1 - children.len();
+        }
+
+        if leaves <= colors {
+            break;
+        }
+    }
+
+    let mut clusters: Vec<([f64; 3], f64)> = vec![];
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        if nodes[node].leaf {
+            if nodes[node].weight > 0.0 {
+                clusters.push((nodes[node].sum, nodes[node].weight));
+            }
+        } else {
+            stack.extend(nodes[node].children.iter().flatten().rev());
+        }
+    }
+
+    // Merging the root would collapse its up to 8 children into a single color, so the leftover
+    // leaves are instead merged pairwise, closest means first
+    let mean = |(sum, weight): ([f64; 3], f64)| sum.map(|value| value / weight);
+    while clusters.len() > colors.max(1) {
+        let mut closest = (0, 1, f64::INFINITY);
+        for i in 0..clusters.len() {
+            for j in i + 1..clusters.len() {
+                let (a, b) = (mean(clusters[i]), mean(clusters[j]));
+                let distance: f64 = (0..3).map(|c| (a[c] - b[c]).powi(2)).sum();
+                if distance < closest.2 {
+                    closest = (i, j, distance);
+                }
+            }
+        }
+
+        let (i, j, _) = closest;
+        let (sum, weight) = clusters.remove(j);
+        for (c, value) in sum.iter().enumerate() {
+            clusters[i].0[c] += value;
+        }
+        clusters[i].1 += weight;
+    }
+
+    clusters.into_iter().map(mean).collect()
+}
+
+/// Build a palette of at most `colors` colors that best represents the image.
+///
+/// Colors are first bucketed into a 15-bit histogram, so very similar colors are treated as one.
+/// Fully transparent pixels are ignored. The result is deterministic.
+/// * `img` - The image::DynamicImage to build the palette from.
+/// * `colors` - Maximum number of colors in the palette.
+/// * `method` - The quantization algorithm.
+pub fn quantize(img: &DynamicImage, colors: usize, method: QuantizeMethod) -> Palette {
+    let entries = histogram(img);
+
+    if entries.is_empty() || colors == 0 {
+        return Palette::new(vec![]);
+    }
+
+    let colors = match method {
+        QuantizeMethod::MedianCut => median_cut(entries, colors),
+        QuantizeMethod::KMeans => k_means(entries, colors),
+        QuantizeMethod::Octree => octree(entries, colors),
+    };
+
+    let mut palette: Vec<Rgb<u8>> = colors.into_iter().map(to_rgb).collect();
+
+    // Different clusters can round to the same color, keep the first of each
+    let mut seen = HashSet::new();
+    palette.retain(|color| seen.insert(color.0));
+
+    Palette::new(palette)
+}
+
+/// Reduce the image to at most `colors` colors, quantizing then mapping to the palette.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `colors` - Maximum number of colors in the output.
+/// * `method` - The quantization algorithm.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn reduce_colors(
+    img: DynamicImage,
+    colors: usize,
+    method: QuantizeMethod,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let palette = quantize(&img, colors, method);
+
+    if palette.colors.is_empty() {
+        return img.into_rgba8();
+    }
+
+    map_to_palette(img, &palette, mix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn four_colors() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(16, 16, |x, y| match (x < 8, y < 8) {
+            (true, true) => Rgba([255, 0, 0, 255]),
+            (false, true) => Rgba([0, 255, 0, 255]),
+            (true, false) => Rgba([0, 0, 255, 255]),
+            (false, false) => Rgba([250, 250, 250, 255]),
+        }))
+    }
+
+    fn noise(seed: u32) -> impl FnMut() -> u8 {
+        let mut state = seed;
+        move || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        }
+    }
+
+    #[test]
+    fn test_from_hex_list() {
+        let palette = Palette::from_hex_list("; comment\n#ff0000 00ff00,\nFF0000FF\n").unwrap();
+
+        assert_eq!(
+            palette.colors,
+            vec![Rgb([255, 0, 0]), Rgb([0, 255, 0]), Rgb([0, 0, 255])]
+        );
+
+        assert!(Palette::from_hex_list("#ff00").is_err());
+        assert!(Palette::from_hex_list("").is_err());
+
+        // Non-ASCII input is an error, not a panic on a char boundary
+        assert!(Palette::from_hex_list("aé€").is_err());
+        assert!(Palette::from_hex_list("aé€ab").is_err());
+    }
+
+    #[test]
+    fn test_from_gpl() {
+        let gpl = "GIMP Palette\nName: Test\nColumns: 2\n#\n255   0   0\tRed\n  0 128 255 Sky\n";
+
+        let palette = Palette::from_gpl(gpl).unwrap();
+        assert_eq!(palette.colors, vec![Rgb([255, 0, 0]), Rgb([0, 128, 255])]);
+
+        assert!(Palette::from_gpl("255 0 0").is_err());
+        assert!(Palette::from_gpl("GIMP Palette\n300 0 0").is_err());
+    }
+
+    #[test]
+    fn test_kd_tree_matches_brute_force() {
+        let mut next = noise(7);
+        let palette = Palette::new((0..40).map(|_| Rgb([next(), next(), next()])).collect());
+        let mapper = PaletteMapper::new(&palette);
+
+        for _ in 0..2000 {
+            let color = Rgb([next(), next(), next()]);
+
+            let distance = |p: &Rgb<u8>| -> i32 {
+                (0..3)
+                    .map(|i| (p.0[i] as i32 - color.0[i] as i32).pow(2))
+                    .sum()
+            };
+            let brute = palette.colors.iter().map(distance).min().unwrap();
+
+            assert_eq!(distance(&mapper.nearest(color)), brute);
+        }
+    }
+
+    #[test]
+    fn test_map_to_palette() {
+        let palette = Palette::new(vec![Rgb([0, 0, 0]), Rgb([255, 255, 255])]);
+
+        let out = map_to_palette(four_colors(), &palette, None);
+
+        assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(0, 8), Rgba([0, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(8, 8), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_quantize_finds_exact_colors() {
+        for method in [
+            QuantizeMethod::MedianCut,
+            QuantizeMethod::KMeans,
+            QuantizeMethod::Octree,
+        ] {
+            let mut palette = quantize(&four_colors(), 4, method).colors;
+            palette.sort_by_key(|color| color.0);
+
+            assert_eq!(
+                palette,
+                vec![
+                    Rgb([0, 0, 255]),
+                    Rgb([0, 255, 0]),
+                    Rgb([250, 250, 250]),
+                    Rgb([255, 0, 0]),
+                ],
+                "{:?}",
+                method
+            );
+
+            // Asking for more colors than the image has never repeats one
+            let palette = quantize(&four_colors(), 8, method).colors;
+            let unique: HashSet<[u8; 3]> = palette.iter().map(|color| color.0).collect();
+            assert_eq!(unique.len(), palette.len(), "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn test_quantize_fewer_colors_than_octants() {
+        // One pixel in each of the eight octants of the RGB cube
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(8, 1, |x, _| {
+            let level = |bit: u32| if x >> bit & 1 == 1 { 200 } else { 50 };
+            Rgba([level(2), level(1), level(0), 255])
+        }));
+
+        for method in [
+            QuantizeMethod::MedianCut,
+            QuantizeMethod::KMeans,
+            QuantizeMethod::Octree,
+        ] {
+            for colors in [1, 2, 4, 7] {
+                let palette = quantize(&img, colors, method).colors;
+                assert_eq!(palette.len(), colors, "{:?}", method);
+            }
+        }
+
+        let palette = quantize(&img, 1, QuantizeMethod::Octree).colors;
+        assert_eq!(palette, vec![Rgb([125, 125, 125])]);
+    }
+
+    #[test]
+    fn test_reduce_colors() {
+        let mut next = noise(3);
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(32, 32, |_, _| {
+            Rgba([next(), next(), next(), 255])
+        }));
+
+        for method in [
+            QuantizeMethod::MedianCut,
+            QuantizeMethod::KMeans,
+            QuantizeMethod::Octree,
+        ] {
+            let out = reduce_colors(img.clone(), 8, method, None);
+
+            let mut colors: Vec<[u8; 4]> = out.pixels().map(|pixel| pixel.0).collect();
+            colors.sort();
+            colors.dedup();
+
+            assert!(
+                colors.len() <= 8,
+                "{:?} gave {} colors",
+                method,
+                colors.len()
+            );
+            assert!(colors.len() > 1, "{:?}", method);
+        }
+    }
+}
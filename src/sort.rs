@@ -160,7 +160,7 @@ pub fn sort(
                 }
 
                 for (col, pixel) in row_pixels.iter().enumerate() {
-                    output.put_pixel(col as u32, row as u32, *pixel);
+                    output.put_pixel(col as u32, row, *pixel);
                 }
             }
         }
@@ -190,7 +190,7 @@ pub fn sort(
                 }
 
                 for (row, pixel) in col_pixels.iter().enumerate() {
-                    output.put_pixel(col as u32, row as u32, *pixel);
+                    output.put_pixel(col, row as u32, *pixel);
                 }
             }
         }
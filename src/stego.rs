@@ -0,0 +1,296 @@
+use crate::utils::get_channel_index_by_name;
+use image::{DynamicImage, RgbaImage};
+
+/// Size of the header written before the payload: a big-endian u32 payload length followed by a
+/// flags byte.
+const HEADER_LEN: usize = 5;
+
+/// Flag set in the header when a CRC-32 of the payload follows it.
+const FLAG_CHECKSUM: u8 = 0b0000_0001;
+
+/// Size of the CRC-32 appended to the payload when checksums are enabled.
+const CHECKSUM_LEN: usize = 4;
+
+/// Parse and validate a channel map and bit count shared by embed and extract.
+fn parse_channel_map(channels: &[String], bits: u8) -> Result<Vec<usize>, String> {
+    if !(1..=8).contains(&bits) {
+        return Err(format!("Invalid number of bits per channel: {}", bits));
+    }
+
+    if channels.is_empty() {
+        return Err("Channel map is empty".to_string());
+    }
+
+    let mut indices: Vec<usize> = vec![];
+
+    for channel in channels {
+        let index = get_channel_index_by_name(channel)
+            .ok_or_else(|| format!("Invalid channel name: {}", channel))?;
+
+        if indices.contains(&index) {
+            return Err(format!("Duplicate channel in channel map: {}", channel));
+        }
+
+        indices.push(index);
+    }
+
+    Ok(indices)
+}
+
+/// CRC-32 (IEEE 802.3, as used by PNG and zip) of the data.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+/// How many payload bytes fit in an image of the given size, after the header and optional
+/// checksum.
+/// * `width` - Width of the cover image.
+/// * `height` - Height of the cover image.
+/// * `channels` - Number of channels in the channel map.
+/// * `bits` - Number of least significant bits used per channel.
+/// * `checksum` - Whether a checksum will be embedded.
+pub fn capacity(width: u32, height: u32, channels: usize, bits: u8, checksum: bool) -> usize {
+    let total_bytes = width as usize * height as usize * channels * bits as usize / 8;
+    let overhead = HEADER_LEN + if checksum { CHECKSUM_LEN } else { 0 };
+
+    total_bytes.saturating_sub(overhead)
+}
+
+/// Embed a byte payload into the least significant bits of an image.
+///
+/// The payload is prefixed with a header holding its length, and optionally followed by a
+/// CRC-32 so extraction can detect a damaged image. Bits are written most significant first,
+/// filling the `bits` lowest bits of each channel in `channels` order, pixel by pixel in row-major
+/// order. Pixels past the end of the data are left untouched.
+///
+/// The output must be stored losslessly (e.g. PNG) for the payload to survive.
+/// * `img` - The image::DynamicImage cover image.
+/// * `payload` - The bytes to hide.
+/// * `channels` - The channels to hide the data in, any of "r", "g", "b" and "a", e.g.
+///   ["r", "g", "b"].
+/// * `bits` - Number of least significant bits used per channel, 1-8.
+/// * `checksum` - If true, append a CRC-32 of the payload.
+pub fn embed(
+    img: DynamicImage,
+    payload: &[u8],
+    channels: Vec<String>,
+    bits: u8,
+    checksum: bool,
+) -> Result<RgbaImage, String> {
+    let indices = parse_channel_map(&channels, bits)?;
+
+    let mut output = img.into_rgba8();
+    let (width, height) = output.dimensions();
+
+    let available = capacity(width, height, indices.len(), bits, checksum);
+    if payload.len() > available || payload.len() > u32::MAX as usize {
+        return Err(format!(
+            "Payload of {} bytes does not fit, the image can hold {} bytes",
+            payload.len(),
+            available
+        ));
+    }
+
+    let mut data: Vec<u8> = Vec::with_capacity(payload.len() + HEADER_LEN + CHECKSUM_LEN);
+    data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    data.push(if checksum { FLAG_CHECKSUM } else { 0 });
+    data.extend_from_slice(payload);
+    if checksum {
+        data.extend_from_slice(&crc32(payload).to_be_bytes());
+    }
+
+    let total_bits = data.len() * 8;
+    let bit_at = |i: usize| {
+        if i < total_bits {
+            data[i / 8] >> (7 - i % 8) & 1
+        } else {
+            0
+        }
+    };
+
+    let mask = (0xFFu16 << bits) as u8;
+    let mut written = 0;
+
+    'pixels: for pixel in output.pixels_mut() {
+        for index in indices.iter() {
+            if written >= total_bits {
+                break 'pixels;
+            }
+
+            let value = (0..bits).fold(0u8, |acc, i| {
+                acc | bit_at(written + i as usize) << (bits - 1 - i)
+            });
+
+            pixel[*index] = pixel[*index] & mask | value;
+            written += bits as usize;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Extract a payload hidden with `embed`. The channel map and bit count must match the ones used
+/// to embed it.
+///
+/// Fails if the header describes a payload larger than the image can hold, or if the payload
+/// carries a checksum that doesn't match.
+/// * `img` - The image::DynamicImage holding the payload.
+/// * `channels` - The channels the data was hidden in, in the same order as when embedding.
+/// * `bits` - Number of least significant bits used per channel, 1-8.
+pub fn extract(img: DynamicImage, channels: Vec<String>, bits: u8) -> Result<Vec<u8>, String> {
+    let indices = parse_channel_map(&channels, bits)?;
+
+    let img = img.into_rgba8();
+    let (width, height) = img.dimensions();
+
+    let mask = (0xFFu16 >> (8 - bits)) as u8;
+
+    // Lazily read the hidden bit stream, most significant first
+    let mut stream = img.pixels().flat_map(|pixel| {
+        indices.iter().flat_map(move |index| {
+            let value = pixel[*index] & mask;
+            (0..bits).rev().map(move |i| value >> i & 1)
+        })
+    });
+
+    let mut read_bytes = |count: usize| -> Result<Vec<u8>, String> {
+        (0..count)
+            .map(|_| {
+                (0..8).try_fold(0u8, |acc, _| match stream.next() {
+                    Some(bit) => Ok(acc << 1 | bit),
+                    None => Err("Image ended before the payload".to_string()),
+                })
+            })
+            .collect()
+    };
+
+    let header = read_bytes(HEADER_LEN)?;
+    let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let has_checksum = header[4] & FLAG_CHECKSUM != 0;
+
+    let available = capacity(width, height, indices.len(), bits, has_checksum);
+    if length > available {
+        return Err(format!(
+            "Header claims {} bytes but the image can hold {}, is the channel map right?",
+            length, available
+        ));
+    }
+
+    let payload = read_bytes(length)?;
+
+    if has_checksum {
+        let stored = read_bytes(CHECKSUM_LEN)?;
+        let stored = u32::from_be_bytes([stored[0], stored[1], stored[2], stored[3]]);
+
+        if stored != crc32(&payload) {
+            return Err("Payload checksum does not match".to_string());
+        }
+    }
+
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, ImageFormat, Rgba};
+    use std::io::Cursor;
+
+    fn cover() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(32, 16, |x, y| {
+            Rgba([(x * 8) as u8, (y * 16) as u8, (x ^ y) as u8, 200])
+        }))
+    }
+
+    fn channel_map(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn png_round_trip(img: RgbaImage) -> DynamicImage {
+        let mut bytes: Vec<u8> = vec![];
+        img.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .expect("Failed to encode PNG.");
+
+        image::load_from_memory_with_format(&bytes, ImageFormat::Png)
+            .expect("Failed to decode PNG.")
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926)
+    }
+
+    #[test]
+    fn test_round_trip_png() {
+        let payload = b"imgfx hides bytes in plain sight".to_vec();
+
+        let stego = embed(cover(), &payload, channel_map(&["r", "g", "b"]), 2, true).unwrap();
+        let decoded = png_round_trip(stego);
+
+        let extracted = extract(decoded, channel_map(&["r", "g", "b"]), 2).unwrap();
+
+        assert_eq!(extracted, payload)
+    }
+
+    #[test]
+    fn test_round_trip_alpha_single_bit() {
+        let payload: Vec<u8> = (0..=40).collect();
+
+        let stego = embed(cover(), &payload, channel_map(&["a", "b"]), 1, false).unwrap();
+
+        // Only the lowest bit of the chosen channels changes
+        for (before, after) in cover().to_rgba8().pixels().zip(stego.pixels()) {
+            assert_eq!((before[0], before[1]), (after[0], after[1]));
+            assert!(before[2].abs_diff(after[2]) <= 1);
+            assert!(before[3].abs_diff(after[3]) <= 1);
+        }
+
+        let extracted = extract(png_round_trip(stego), channel_map(&["a", "b"]), 1).unwrap();
+
+        assert_eq!(extracted, payload)
+    }
+
+    #[test]
+    fn test_capacity() {
+        let available = capacity(32, 16, 3, 2, true);
+        assert_eq!(available, 32 * 16 * 3 * 2 / 8 - 9);
+
+        let payload = vec![0u8; available];
+        assert!(embed(cover(), &payload, channel_map(&["r", "g", "b"]), 2, true).is_ok());
+
+        let payload = vec![0u8; available + 1];
+        assert!(embed(cover(), &payload, channel_map(&["r", "g", "b"]), 2, true).is_err());
+    }
+
+    #[test]
+    fn test_checksum_detects_damage() {
+        let payload = b"do not touch".to_vec();
+
+        let mut stego = embed(cover(), &payload, channel_map(&["r"]), 8, true).unwrap();
+
+        // With 8 bits in one channel, the first payload byte is the red of pixel 5
+        stego.get_pixel_mut(5, 0)[0] ^= 0b0000_0100;
+
+        let result = extract(DynamicImage::ImageRgba8(stego), channel_map(&["r"]), 8);
+
+        assert_eq!(result, Err("Payload checksum does not match".to_string()))
+    }
+
+    #[test]
+    fn test_invalid_channel_map() {
+        assert!(embed(cover(), b"x", channel_map(&["r", "r"]), 1, false).is_err());
+        assert!(embed(cover(), b"x", channel_map(&["x"]), 1, false).is_err());
+        assert!(embed(cover(), b"x", channel_map(&["r"]), 0, false).is_err());
+        assert!(extract(cover(), channel_map(&[]), 1).is_err());
+    }
+}
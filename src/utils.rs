@@ -1,4 +1,7 @@
-use image::{Rgb, Rgba};
+use std::str::FromStr;
+
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba, RgbaImage};
+use rayon::prelude::*;
 
 pub fn get_channel_by_name_rgb_color(name: &str, color: &Rgb<u8>) -> u8 {
     match name {
@@ -18,7 +21,57 @@ pub fn get_channel_by_name_rgba_u8(name: &str, color: &Rgba<u8>) -> u8 {
     }
 }
 
+/// Index of a channel name in an Rgba pixel, "r", "g", "b" or "a".
+pub fn get_channel_index_by_name(name: &str) -> Option<usize> {
+    match name {
+        "r" => Some(0),
+        "g" => Some(1),
+        "b" => Some(2),
+        "a" => Some(3),
+        _ => None,
+    }
+}
+
+/// An RGB channel, named "r", "g" or "b" like the lhs and rhs channel remapping.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Channel {
+    R,
+    G,
+    B,
+}
+
+/// Clap FromStr
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "r" => Ok(Channel::R),
+            "g" => Ok(Channel::G),
+            "b" => Ok(Channel::B),
+
+            _ => Err(format!("Invalid Channel name: {}", s)),
+        }
+    }
+}
+
+impl Channel {
+    /// Index of the channel in an Rgb or Rgba pixel.
+    pub fn index(self) -> usize {
+        match self {
+            Channel::R => 0,
+            Channel::G => 1,
+            Channel::B => 2,
+        }
+    }
+}
+
 pub fn hex_to_rgb(hex: &str) -> Option<(u8, u8, u8)> {
+    // The byte slicing below is only valid on ASCII
+    if !hex.is_ascii() {
+        return None;
+    }
+
     if hex.starts_with('#') && hex.len() == 7 {
         let r = u8::from_str_radix(&hex[1..3], 16).ok()?;
         let g = u8::from_str_radix(&hex[3..5], 16).ok()?;
@@ -63,7 +116,487 @@ pub fn rgb_to_hsv(rgb: Rgba<u8>) -> (f64, f64, f64) {
 }
 
 pub fn calc_luminance(color: Rgba<u8>) -> f64 {
-    let luminance =
-        0.2126 * (color[0] as f64) + 0.7152 * (color[1] as f64) + 0.0722 * (color[2] as f64);
-    luminance
+    0.2126 * (color[0] as f64) + 0.7152 * (color[1] as f64) + 0.0722 * (color[2] as f64)
+}
+
+/// Strength of an operation per RGB channel. 0.0 leaves the channel untouched, 1.0 applies the
+/// full effect and anything in between linearly mixes the result with the original pixel, after
+/// any lhs remapping. 0.0 therefore gives the remapped image rather than the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mix(pub [f64; 3]);
+
+impl Mix {
+    /// The same strength for all three channels.
+    pub fn uniform(amount: f64) -> Self {
+        Mix([amount; 3])
+    }
+
+    /// Separate strengths for the R, G and B channels.
+    pub fn per_channel(r: f64, g: f64, b: f64) -> Self {
+        Mix([r, g, b])
+    }
+
+    /// Mix the result of an operation with the (remapped) original pixel. Alpha is taken from the
+    /// result.
+    pub fn apply(self, original: Rgba<u8>, result: Rgba<u8>) -> Rgba<u8> {
+        let channel = |i: usize| {
+            let amount = self.0[i].clamp(0.0, 1.0);
+            (original[i] as f64 + (result[i] as f64 - original[i] as f64) * amount).round() as u8
+        };
+
+        Rgba([channel(0), channel(1), channel(2), result[3]])
+    }
+}
+
+/// Clap FromStr, either a single amount or three comma separated amounts.
+impl FromStr for Mix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let amounts = s
+            .split(',')
+            .map(|amount| amount.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| format!("Invalid Mix: {}", s))?;
+
+        match amounts.as_slice() {
+            [amount] => Ok(Mix::uniform(*amount)),
+            [r, g, b] => Ok(Mix::per_channel(*r, *g, *b)),
+
+            _ => Err(format!("Invalid Mix: {}", s)),
+        }
+    }
+}
+
+/// Apply an optional Mix, None being full strength.
+pub fn mix_pixels(original: Rgba<u8>, result: Rgba<u8>, mix: Option<Mix>) -> Rgba<u8> {
+    match mix {
+        Some(mix) => mix.apply(original, result),
+        None => result,
+    }
+}
+
+/// A 256 entry lookup table for each of the R, G and B channels.
+pub type ChannelLuts = [[u8; 256]; 3];
+
+/// Map every pixel through per channel lookup tables. Alpha is passed through.
+/// * `img` - The image::DynamicImage input to perform the operation on.
+/// * `lhs` - Optional vector of Strings choosing the source channel of each output channel.
+/// * `luts` - The lookup tables of the R, G and B output channels.
+/// * `mix` - Optional strength of the operation per channel, see Mix. None is full strength.
+pub fn apply_luts(
+    img: DynamicImage,
+    lhs: Option<Vec<String>>,
+    luts: &ChannelLuts,
+    mix: Option<Mix>,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+
+    let mut output: RgbaImage = ImageBuffer::new(width, height);
+
+    output.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let in_pixel = img.get_pixel(x, y);
+
+        let lhs = match lhs {
+            Some(ref lhs) => [
+                get_channel_by_name_rgba_u8(&lhs[0], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[1], &in_pixel),
+                get_channel_by_name_rgba_u8(&lhs[2], &in_pixel),
+            ],
+            None => [in_pixel[0], in_pixel[1], in_pixel[2]],
+        };
+
+        let out = Rgba([
+            luts[0][lhs[0] as usize],
+            luts[1][lhs[1] as usize],
+            luts[2][lhs[2] as usize],
+            in_pixel[3],
+        ]);
+
+        *pixel = mix_pixels(Rgba([lhs[0], lhs[1], lhs[2], in_pixel[3]]), out, mix);
+    });
+
+    output
+}
+
+/// How the result of an operation that falls outside of 0-255 is brought back into a channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Overflow {
+    /// Saturate at 0 and 255.
+    Clamp,
+    /// Keep the low 8 bits, wrapping around like u8 arithmetic.
+    Wrap,
+    /// Fold back at the edges, so 256 becomes 254 and -1 becomes 1.
+    Mirror,
+    /// Take the absolute value, then saturate at 255.
+    AbsDiff,
+}
+
+/// Clap FromStr
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clamp" => Ok(Overflow::Clamp),
+            "wrap" => Ok(Overflow::Wrap),
+            "mirror" => Ok(Overflow::Mirror),
+            "fold" => Ok(Overflow::Mirror),
+            "absdiff" => Ok(Overflow::AbsDiff),
+            "abs" => Ok(Overflow::AbsDiff),
+
+            _ => Err(format!("Invalid Overflow name: {}", s)),
+        }
+    }
+}
+
+impl Overflow {
+    /// Bring a value computed at full precision back into a u8 channel.
+    pub fn apply(self, value: i64) -> u8 {
+        match self {
+            Overflow::Clamp => value.clamp(0, 255) as u8,
+            Overflow::Wrap => value.rem_euclid(256) as u8,
+            Overflow::Mirror => {
+                let folded = value.rem_euclid(510);
+                if folded > 255 {
+                    (510 - folded) as u8
+                } else {
+                    folded as u8
+                }
+            }
+            Overflow::AbsDiff => value.unsigned_abs().min(255) as u8,
+        }
+    }
+}
+
+/// The right-hand side of an operation, either a single color or a second image.
+#[derive(Clone)]
+pub enum Operand {
+    Color(Rgb<u8>),
+    /// An image operand is sampled at the same coordinates as the input image and tiled if it is
+    /// smaller than the input. Operations only apply the lhs remapping for an empty operand image.
+    Image(DynamicImage),
+}
+
+impl From<Rgb<u8>> for Operand {
+    fn from(color: Rgb<u8>) -> Self {
+        Operand::Color(color)
+    }
+}
+
+impl From<DynamicImage> for Operand {
+    fn from(img: DynamicImage) -> Self {
+        Operand::Image(img)
+    }
+}
+
+impl Operand {
+    /// Whether the operand is an image without any pixels.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Operand::Color(_) => false,
+            Operand::Image(img) => img.width() == 0 || img.height() == 0,
+        }
+    }
+
+    /// The operand's RGB at the given coordinates, remapped by `rhs`.
+    pub fn get_rgb(&self, x: u32, y: u32, rhs: &Option<Vec<String>>) -> (u8, u8, u8) {
+        let pixel = match self {
+            Operand::Color(color) => Rgba([color.0[0], color.0[1], color.0[2], 255]),
+            Operand::Image(img) => {
+                let (width, height) = img.dimensions();
+                img.get_pixel(x % width, y % height)
+            }
+        };
+
+        match rhs {
+            Some(rhs) => (
+                get_channel_by_name_rgba_u8(&rhs[0], &pixel),
+                get_channel_by_name_rgba_u8(&rhs[1], &pixel),
+                get_channel_by_name_rgba_u8(&rhs[2], &pixel),
+            ),
+            None => (pixel[0], pixel[1], pixel[2]),
+        }
+    }
+}
+
+/// What to sample when reading outside of the image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EdgeMode {
+    /// Repeat the nearest edge pixel.
+    Clamp,
+    /// Tile the image.
+    Wrap,
+    /// Reflect the image at its edges, without repeating the edge pixel.
+    Mirror,
+    /// Fully transparent black.
+    Transparent,
+    /// A fixed color.
+    Constant(Rgba<u8>),
+}
+
+/// Clap FromStr. "constant" is opaque black, a hex color is a constant of that color.
+impl FromStr for EdgeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clamp" => Ok(EdgeMode::Clamp),
+            "wrap" => Ok(EdgeMode::Wrap),
+            "mirror" => Ok(EdgeMode::Mirror),
+            "transparent" => Ok(EdgeMode::Transparent),
+            "constant" => Ok(EdgeMode::Constant(Rgba([0, 0, 0, 255]))),
+
+            hex => match hex_to_rgb(hex) {
+                Some((r, g, b)) => Ok(EdgeMode::Constant(Rgba([r, g, b, 255]))),
+                None => Err(format!("Invalid EdgeMode name: {}", s)),
+            },
+        }
+    }
+}
+
+impl EdgeMode {
+    /// Resolve a possibly out of bounds coordinate to a pixel, None meaning the `fill` color.
+    pub fn resolve(self, x: i64, y: i64, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (w, h) = (width as i64, height as i64);
+
+        if w == 0 || h == 0 {
+            return None;
+        }
+
+        let mirror = |v: i64, size: i64| {
+            if size == 1 {
+                return 0;
+            }
+
+            let period = 2 * (size - 1);
+            let v = v.rem_euclid(period);
+            if v >= size {
+                period - v
+            } else {
+                v
+            }
+        };
+
+        match self {
+            EdgeMode::Clamp => Some((x.clamp(0, w - 1) as u32, y.clamp(0, h - 1) as u32)),
+            EdgeMode::Wrap => Some((x.rem_euclid(w) as u32, y.rem_euclid(h) as u32)),
+            EdgeMode::Mirror => Some((mirror(x, w) as u32, mirror(y, h) as u32)),
+            EdgeMode::Transparent | EdgeMode::Constant(_) => {
+                if x < 0 || y < 0 || x >= w || y >= h {
+                    None
+                } else {
+                    Some((x as u32, y as u32))
+                }
+            }
+        }
+    }
+
+    /// The color of coordinates that don't resolve to a pixel.
+    pub fn fill(self) -> Rgba<u8> {
+        match self {
+            EdgeMode::Constant(color) => color,
+            _ => Rgba([0, 0, 0, 0]),
+        }
+    }
+}
+
+/// Sample an image at sub-pixel coordinates with bilinear interpolation. Pixel centers sit on
+/// whole coordinates. Returns the RGBA channels as f64 in 0.0-255.0.
+///
+/// Colors are interpolated premultiplied by alpha, so transparent pixels (and a transparent
+/// edge) don't darken the color of the opaque pixels next to them.
+pub fn sample_bilinear(img: &RgbaImage, x: f64, y: f64, edge: EdgeMode) -> [f64; 4] {
+    let (width, height) = img.dimensions();
+
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+
+    let texel = |dx: i64, dy: i64| -> [f64; 4] {
+        let pixel = match edge.resolve(x0 as i64 + dx, y0 as i64 + dy, width, height) {
+            Some((px, py)) => *img.get_pixel(px, py),
+            None => edge.fill(),
+        };
+
+        let alpha = pixel[3] as f64 / 255.0;
+        [
+            pixel[0] as f64 * alpha,
+            pixel[1] as f64 * alpha,
+            pixel[2] as f64 * alpha,
+            pixel[3] as f64,
+        ]
+    };
+
+    let (tl, tr, bl, br) = (texel(0, 0), texel(1, 0), texel(0, 1), texel(1, 1));
+
+    let mut out = [0.0; 4];
+    for i in 0..4 {
+        let top = tl[i] + (tr[i] - tl[i]) * fx;
+        let bottom = bl[i] + (br[i] - bl[i]) * fx;
+        out[i] = top + (bottom - top) * fy;
+    }
+
+    // Back to straight alpha
+    if out[3] > 0.0 {
+        let alpha = out[3] / 255.0;
+        for channel in out.iter_mut().take(3) {
+            *channel = (*channel / alpha).min(255.0);
+        }
+    }
+
+    out
+}
+
+/// Convert a normalised sRGB channel to linear light.
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a normalised linear light channel to sRGB.
+pub fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// D65 reference white used by the CIE Lab conversions.
+const D65_WHITE: (f64, f64, f64) = (0.95047, 1.0, 1.08883);
+
+/// Convert an sRGB color to CIE L*a*b* (D65). L* is 0-100.
+pub fn rgb_to_lab(color: Rgb<u8>) -> (f64, f64, f64) {
+    let [r, g, b] = color
+        .0
+        .map(|channel| srgb_to_linear(channel as f64 / 255.0));
+
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / D65_WHITE.0;
+    let y = (0.2126729 * r + 0.7151522 * g + 0.0721750 * b) / D65_WHITE.1;
+    let z = (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / D65_WHITE.2;
+
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Convert a CIE L*a*b* (D65) color back to sRGB, clamping colors outside of the gamut.
+pub fn lab_to_rgb(lab: (f64, f64, f64)) -> Rgb<u8> {
+    let fy = (lab.0 + 16.0) / 116.0;
+    let fx = fy + lab.1 / 500.0;
+    let fz = fy - lab.2 / 200.0;
+
+    let f_inv = |t: f64| {
+        if t.powi(3) > 216.0 / 24389.0 {
+            t.powi(3)
+        } else {
+            (116.0 * t - 16.0) / (24389.0 / 27.0)
+        }
+    };
+
+    let x = f_inv(fx) * D65_WHITE.0;
+    let y = f_inv(fy) * D65_WHITE.1;
+    let z = f_inv(fz) * D65_WHITE.2;
+
+    let r = 3.2404542 * x - 1.5371385 * y - 0.4985314 * z;
+    let g = -0.9692660 * x + 1.8760108 * y + 0.0415560 * z;
+    let b = 0.0556434 * x - 0.2040259 * y + 1.0572252 * z;
+
+    Rgb([r, g, b].map(|channel| {
+        (linear_to_srgb(channel.clamp(0.0, 1.0)) * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    }))
+}
+
+/// Convert linear sRGB (0.0-1.0) to Oklab.
+pub fn linear_srgb_to_oklab(c: [f64; 3]) -> [f64; 3] {
+    let l = 0.4122214708 * c[0] + 0.5363325363 * c[1] + 0.0514459929 * c[2];
+    let m = 0.2119034982 * c[0] + 0.6806995451 * c[1] + 0.1073969566 * c[2];
+    let s = 0.0883024619 * c[0] + 0.2817188376 * c[1] + 0.6299787005 * c[2];
+
+    let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+/// Convert Oklab back to linear sRGB. Colors outside of the gamut are not clamped.
+pub fn oklab_to_linear_srgb(c: [f64; 3]) -> [f64; 3] {
+    let l = c[0] + 0.3963377774 * c[1] + 0.2158037573 * c[2];
+    let m = c[0] - 0.1055613458 * c[1] - 0.0638541728 * c[2];
+    let s = c[0] - 0.0894841775 * c[1] - 1.2914855480 * c[2];
+
+    let (l, m, s) = (l.powi(3), m.powi(3), s.powi(3));
+
+    [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ]
+}
+
+/// Small seeded pseudo random generator (SplitMix64), for effects that need repeatable noise.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in 0.0..1.0.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform integer in 0..bound. Panics if bound is 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "Rng bound must be positive");
+        self.next_u64() % bound
+    }
+}
+
+/// Images shared by the module tests.
+#[cfg(test)]
+pub(crate) mod test_images {
+    use image::{DynamicImage, ImageBuffer, Rgba};
+
+    /// An opaque or translucent image of a single color.
+    pub fn solid(width: u32, height: u32, color: Rgba<u8>) -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_pixel(width, height, color))
+    }
+
+    /// Opaque gradient. Red grows by 10 per column, green by 10 per row and blue is the red plus
+    /// the row, wrapping past 255. Every pixel is unique for images up to 128x128.
+    pub fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(width, height, |x, y| {
+            Rgba([(x * 10) as u8, (y * 10) as u8, (x * 10 + y) as u8, 255])
+        }))
+    }
 }